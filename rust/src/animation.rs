@@ -0,0 +1,188 @@
+extern crate cgmath;
+
+use cgmath::*;
+
+use crate::buffers::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+// Keyframe values are stored flat with `components` floats each. Cubic spline samplers
+// store an in-tangent, the value and an out-tangent for every keyframe, in that order.
+pub struct AnimationSampler {
+    pub times: Vec<f32>,
+    pub values: Vec<f32>,
+    pub components: usize,
+    pub interpolation: Interpolation,
+}
+
+impl AnimationSampler {
+    pub fn new(
+        times: Vec<f32>,
+        values: Vec<f32>,
+        components: usize,
+        interpolation: Interpolation,
+    ) -> Result<Self, String> {
+        let values_per_key = match interpolation {
+            Interpolation::CubicSpline => 3 * components,
+            _ => components,
+        };
+        if times.is_empty() {
+            return Err("Animation sampler has no keyframes".to_string());
+        }
+        if values.len() != times.len() * values_per_key {
+            return Err(format!(
+                "Animation sampler has {} keyframes but {} values, expected {}",
+                times.len(),
+                values.len(),
+                times.len() * values_per_key
+            ));
+        }
+        if times.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err("Animation sampler keyframe times are not increasing".to_string());
+        }
+        Ok(Self {
+            times,
+            values,
+            components,
+            interpolation,
+        })
+    }
+
+    pub fn duration(&self) -> f32 {
+        *self.times.last().unwrap()
+    }
+
+    fn element(&self, keyframe: usize, element: usize) -> &[f32] {
+        let start = match self.interpolation {
+            Interpolation::CubicSpline => (3 * keyframe + element) * self.components,
+            _ => keyframe * self.components,
+        };
+        &self.values[start..start + self.components]
+    }
+
+    fn value(&self, keyframe: usize) -> &[f32] {
+        self.element(keyframe, 1)
+    }
+
+    // Finds the keyframes around `time` and the normalized position between them.
+    // Times outside the animated range clamp to the first or last keyframe.
+    fn locate(&self, time: f32) -> Result<(usize, usize, f32, f32), usize> {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return Err(0);
+        }
+        if time >= self.times[last] {
+            return Err(last);
+        }
+        let next = self.times.iter().position(|t| *t > time).unwrap();
+        let prev = next - 1;
+        let delta = self.times[next] - self.times[prev];
+        Ok((prev, next, (time - self.times[prev]) / delta, delta))
+    }
+
+    fn hermite(&self, prev: usize, next: usize, t: f32, delta: f32) -> Vec<f32> {
+        let (t2, t3) = (t * t, t * t * t);
+        let p0 = self.value(prev);
+        let m0 = self.element(prev, 2);
+        let p1 = self.value(next);
+        let m1 = self.element(next, 0);
+        (0..self.components)
+            .map(|i| {
+                (2.0 * t3 - 3.0 * t2 + 1.0) * p0[i]
+                    + (t3 - 2.0 * t2 + t) * delta * m0[i]
+                    + (-2.0 * t3 + 3.0 * t2) * p1[i]
+                    + (t3 - t2) * delta * m1[i]
+            })
+            .collect()
+    }
+
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let (prev, next, t, delta) = match self.locate(time) {
+            Ok(located) => located,
+            Err(keyframe) => return self.value(keyframe).to_vec(),
+        };
+        match self.interpolation {
+            Interpolation::Step => self.value(prev).to_vec(),
+            Interpolation::Linear => self
+                .value(prev)
+                .iter()
+                .zip(self.value(next))
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+            Interpolation::CubicSpline => self.hermite(prev, next, t, delta),
+        }
+    }
+
+    // Rotations are stored as glTF (x, y, z, w) quaternions. Linear keyframes are
+    // slerped along the shortest arc, cubic ones renormalized after interpolation.
+    pub fn sample_rotation(&self, time: f32) -> Quaternion<f32> {
+        let quaternion = |v: &[f32]| Quaternion::new(v[3], v[0], v[1], v[2]);
+        let (prev, next, t, delta) = match self.locate(time) {
+            Ok(located) => located,
+            Err(keyframe) => return quaternion(self.value(keyframe)).normalize(),
+        };
+        match self.interpolation {
+            Interpolation::Step => quaternion(self.value(prev)).normalize(),
+            Interpolation::Linear => {
+                let from = quaternion(self.value(prev)).normalize();
+                let to = quaternion(self.value(next)).normalize();
+                let to = if from.dot(to) < 0.0 { -to } else { to };
+                from.slerp(to, t)
+            }
+            Interpolation::CubicSpline => {
+                quaternion(&self.hermite(prev, next, t, delta)).normalize()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationProperty {
+    Translation,
+    Rotation,
+    Scale,
+    Weights,
+}
+
+pub struct AnimationChannel {
+    pub node: usize,
+    pub property: AnimationProperty,
+    pub sampler: AnimationSampler,
+}
+
+pub struct Animation {
+    pub name: Option<String>,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl Animation {
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(|channel| channel.sampler.duration())
+            .fold(0.0, f32::max)
+    }
+}
+
+pub const MAX_JOINTS: usize = 128;
+
+// Joint matrices of the skin being drawn, mirrors the Skin block in shaders/skin.glsl.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SkinBlock {
+    pub joint_matrices: [Matrix4<f32>; MAX_JOINTS],
+}
+
+impl UniformBlock for SkinBlock {
+    const LAYOUT: UniformBlockLayout = UniformBlockLayout {
+        name: "Skin",
+        binding: 1,
+        size: std::mem::size_of::<SkinBlock>(),
+        members: &[("joint_matrices[0]", 0)],
+    };
+}
@@ -1,28 +1,93 @@
 extern crate gl;
 
 use std::convert::TryInto;
+use std::marker::PhantomData;
+
+use crate::context::*;
+use crate::textures::*;
+
+fn upload_buffer_data<T: Copy>(target: gl::types::GLenum, data: &[T], usage: gl::types::GLenum) {
+    unsafe {
+        gl::BufferData(
+            target,
+            std::mem::size_of_val(data).try_into().unwrap(),
+            data.as_ptr() as *const std::ffi::c_void,
+            usage,
+        );
+    }
+}
+
+fn upload_buffer_sub_data<T: Copy>(
+    target: gl::types::GLenum,
+    byte_offset: usize,
+    capacity: usize,
+    data: &[T],
+) {
+    let size = std::mem::size_of_val(data);
+    assert!(
+        byte_offset + size <= capacity,
+        "buffer update of {} bytes at offset {} overflows capacity of {} bytes",
+        size,
+        byte_offset,
+        capacity
+    );
+    unsafe {
+        gl::BufferSubData(
+            target,
+            byte_offset.try_into().unwrap(),
+            size.try_into().unwrap(),
+            data.as_ptr() as *const std::ffi::c_void,
+        );
+    }
+}
+
+// Reallocates the storage so the driver does not have to wait for draw calls still
+// reading the old contents.
+fn orphan_buffer(target: gl::types::GLenum, capacity: usize, usage: gl::types::GLenum) {
+    unsafe {
+        gl::BufferData(
+            target,
+            capacity.try_into().unwrap(),
+            std::ptr::null(),
+            usage,
+        );
+    }
+}
 
 pub struct VertexBuffer {
     id: gl::types::GLuint,
+    capacity: usize,
+    usage: gl::types::GLenum,
 }
 
 impl VertexBuffer {
-    pub fn new_static(vertices: &[f32]) -> Self {
-        let mut vb = Self { id: 0 };
+    pub fn new_static<T: Copy>(vertices: &[T]) -> Self {
+        Self::new_with_usage(vertices, gl::STATIC_DRAW)
+    }
+
+    pub fn new_dynamic<T: Copy>(vertices: &[T]) -> Self {
+        Self::new_with_usage(vertices, gl::DYNAMIC_DRAW)
+    }
+
+    // Uninitialized storage for data rewritten every frame.
+    pub fn new_stream(capacity: usize) -> Self {
+        let mut vb = Self::new_with_usage::<u8>(&[], gl::STREAM_DRAW);
+        vb.capacity = capacity;
+        vb.orphan();
+        vb
+    }
+
+    fn new_with_usage<T: Copy>(vertices: &[T], usage: gl::types::GLenum) -> Self {
+        let mut vb = Self {
+            id: 0,
+            capacity: std::mem::size_of_val(vertices),
+            usage,
+        };
         unsafe {
             gl::GenBuffers(1, &mut vb.id);
         }
         vb.bind();
-        unsafe {
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertices.len() * std::mem::size_of::<f32>())
-                    .try_into()
-                    .unwrap(),
-                vertices.as_ptr() as *const std::ffi::c_void,
-                gl::STATIC_DRAW,
-            );
-        }
+        upload_buffer_data(gl::ARRAY_BUFFER, vertices, usage);
         vb
     }
 
@@ -31,6 +96,35 @@ impl VertexBuffer {
             gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
         }
     }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn update_sub_data<T: Copy>(&self, byte_offset: usize, vertices: &[T]) {
+        self.bind();
+        upload_buffer_sub_data(gl::ARRAY_BUFFER, byte_offset, self.capacity, vertices);
+    }
+
+    pub fn orphan(&self) {
+        self.bind();
+        orphan_buffer(gl::ARRAY_BUFFER, self.capacity, self.usage);
+    }
+
+    // Replaces the whole contents, growing the storage when needed.
+    pub fn update<T: Copy>(&mut self, vertices: &[T]) {
+        self.bind();
+        let size = std::mem::size_of_val(vertices);
+        if size > self.capacity {
+            self.capacity = size;
+        }
+        orphan_buffer(gl::ARRAY_BUFFER, self.capacity, self.usage);
+        upload_buffer_sub_data(gl::ARRAY_BUFFER, 0, self.capacity, vertices);
+    }
 }
 
 impl Drop for VertexBuffer {
@@ -41,6 +135,123 @@ impl Drop for VertexBuffer {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributeKind {
+    // Read as floats in the shader, from floats or from integers converted as is.
+    Float,
+    // Integers mapped to [0, 1] when unsigned or [-1, 1] when signed.
+    Normalized,
+    // Integers read as int, ivec or uvec in the shader.
+    Integer,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub components: i32,
+    pub data_type: gl::types::GLenum,
+    pub kind: AttributeKind,
+    pub offset: usize,
+}
+
+// Interleaved attributes in declaration order, offsets and the stride are computed from
+// the attribute types.
+#[derive(Clone, Debug)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+    stride: i32,
+    divisor: u32,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self {
+            attributes: Vec::new(),
+            stride: 0,
+            divisor: 0,
+        }
+    }
+
+    pub fn attribute(
+        mut self,
+        location: u32,
+        components: i32,
+        data_type: gl::types::GLenum,
+        kind: AttributeKind,
+    ) -> Self {
+        let size = attribute_size(data_type, components);
+        self.attributes.push(VertexAttribute {
+            location,
+            components,
+            data_type,
+            kind,
+            offset: self.stride as usize,
+        });
+        self.stride += size as i32;
+        self
+    }
+
+    pub fn float(self, location: u32, components: i32) -> Self {
+        self.attribute(location, components, gl::FLOAT, AttributeKind::Float)
+    }
+
+    pub fn normalized(self, location: u32, components: i32, data_type: gl::types::GLenum) -> Self {
+        self.attribute(location, components, data_type, AttributeKind::Normalized)
+    }
+
+    pub fn integer(self, location: u32, components: i32, data_type: gl::types::GLenum) -> Self {
+        self.attribute(location, components, data_type, AttributeKind::Integer)
+    }
+
+    // Normal packed into a single 32 bit value, read as a normalized vec4.
+    pub fn packed_normal(self, location: u32) -> Self {
+        self.normalized(location, 4, gl::INT_2_10_10_10_REV)
+    }
+
+    // Matrix columns take four consecutive locations.
+    pub fn mat4(self, location: u32) -> Self {
+        (0..4).fold(self, |layout, column| layout.float(location + column, 4))
+    }
+
+    // Advances the attributes once per instance instead of once per vertex.
+    pub fn per_instance(mut self) -> Self {
+        self.divisor = 1;
+        self
+    }
+
+    // Skips bytes the shader does not read, e.g. struct padding.
+    pub fn padding(mut self, bytes: usize) -> Self {
+        self.stride += bytes as i32;
+        self
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride as usize
+    }
+}
+
+fn attribute_size(data_type: gl::types::GLenum, components: i32) -> usize {
+    let components = components as usize;
+    match data_type {
+        gl::BYTE | gl::UNSIGNED_BYTE => components,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2 * components,
+        gl::INT | gl::UNSIGNED_INT | gl::FLOAT => 4 * components,
+        gl::DOUBLE => 8 * components,
+        gl::INT_2_10_10_10_REV | gl::UNSIGNED_INT_2_10_10_10_REV => 4,
+        _ => panic!("Unsupported vertex attribute type: 0x{:x}", data_type),
+    }
+}
+
+// Implemented by `#[repr(C)]` vertex structs, the layout has to describe every field
+// in declaration order.
+pub trait Vertex: Copy {
+    fn layout() -> VertexLayout;
+}
+
 pub struct VertexArray {
     id: gl::types::GLuint,
 }
@@ -61,34 +272,44 @@ impl VertexArray {
         }
     }
 
-    pub fn set_vertex_attrib_array(
-        &self,
-        index: u32,
-        count: i32,
-        normalized: bool,
-        stride: i32,
-        offset: i32,
-    ) {
-        let norm = {
-            if normalized {
-                gl::TRUE
-            } else {
-                gl::FALSE
-            }
-        };
+    // Sets up the attribute pointers for the buffer currently bound to GL_ARRAY_BUFFER.
+    pub fn set_layout(&self, layout: &VertexLayout) {
         self.bind();
-        unsafe {
-            gl::VertexAttribPointer(
-                index,
-                count,
-                gl::FLOAT,
-                norm,
-                stride * std::mem::size_of::<f32>() as i32,
-                (offset * std::mem::size_of::<f32>() as i32) as *const std::ffi::c_void,
-            );
-            gl::EnableVertexAttribArray(index);
+        for attribute in &layout.attributes {
+            let offset = attribute.offset as *const std::ffi::c_void;
+            unsafe {
+                match attribute.kind {
+                    AttributeKind::Integer => gl::VertexAttribIPointer(
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        layout.stride,
+                        offset,
+                    ),
+                    AttributeKind::Float | AttributeKind::Normalized => gl::VertexAttribPointer(
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        (attribute.kind == AttributeKind::Normalized) as gl::types::GLboolean,
+                        layout.stride,
+                        offset,
+                    ),
+                }
+                gl::VertexAttribDivisor(attribute.location, layout.divisor);
+                gl::EnableVertexAttribArray(attribute.location);
+            }
         }
     }
+
+    pub fn set_vertex_type<T: Vertex>(&self) {
+        let layout = T::layout();
+        assert_eq!(
+            layout.stride(),
+            std::mem::size_of::<T>(),
+            "vertex layout does not cover the whole vertex struct"
+        );
+        self.set_layout(&layout);
+    }
 }
 
 impl Drop for VertexArray {
@@ -99,31 +320,64 @@ impl Drop for VertexArray {
     }
 }
 
+pub trait Index: Copy {
+    const GL_TYPE: gl::types::GLenum;
+}
+
+impl Index for u8 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl Index for u16 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl Index for u32 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_INT;
+}
+
 pub struct IndexBuffer {
     id: gl::types::GLuint,
     count: usize,
+    index_type: gl::types::GLenum,
+    capacity: usize,
+    usage: gl::types::GLenum,
 }
 
 impl IndexBuffer {
-    pub fn new_static(indices: &[u32]) -> Self {
+    pub fn new_static<T: Index>(indices: &[T]) -> Self {
+        Self::new_with_usage(indices, gl::STATIC_DRAW)
+    }
+
+    pub fn new_dynamic<T: Index>(indices: &[T]) -> Self {
+        Self::new_with_usage(indices, gl::DYNAMIC_DRAW)
+    }
+
+    // Stores the indices as u8 or u16 when all of them fit.
+    pub fn new_static_narrowest(indices: &[u32]) -> Self {
+        let max = indices.iter().cloned().max().unwrap_or(0);
+        if max <= u8::MAX as u32 {
+            Self::new_static(&indices.iter().map(|i| *i as u8).collect::<Vec<_>>())
+        } else if max <= u16::MAX as u32 {
+            Self::new_static(&indices.iter().map(|i| *i as u16).collect::<Vec<_>>())
+        } else {
+            Self::new_static(indices)
+        }
+    }
+
+    fn new_with_usage<T: Index>(indices: &[T], usage: gl::types::GLenum) -> Self {
         let mut ib = Self {
             id: 0,
             count: indices.len(),
+            index_type: T::GL_TYPE,
+            capacity: std::mem::size_of_val(indices),
+            usage,
         };
         unsafe {
             gl::GenBuffers(1, &mut ib.id);
         }
         ib.bind();
-        unsafe {
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (ib.count() * std::mem::size_of::<u32>())
-                    .try_into()
-                    .unwrap(),
-                indices.as_ptr() as *const std::ffi::c_void,
-                gl::STATIC_DRAW,
-            );
-        }
+        upload_buffer_data(gl::ELEMENT_ARRAY_BUFFER, indices, usage);
         ib
     }
 
@@ -136,6 +390,46 @@ impl IndexBuffer {
     pub fn count(&self) -> usize {
         self.count
     }
+
+    // GL_UNSIGNED_BYTE, GL_UNSIGNED_SHORT or GL_UNSIGNED_INT, as passed to glDrawElements.
+    pub fn index_type(&self) -> gl::types::GLenum {
+        self.index_type
+    }
+
+    pub fn update_sub_data<T: Index>(&self, first_index: usize, indices: &[T]) {
+        assert_eq!(
+            T::GL_TYPE,
+            self.index_type,
+            "index type does not match the type of index buffer {}",
+            self.id
+        );
+        self.bind();
+        upload_buffer_sub_data(
+            gl::ELEMENT_ARRAY_BUFFER,
+            first_index * std::mem::size_of::<T>(),
+            self.capacity,
+            indices,
+        );
+    }
+
+    pub fn orphan(&self) {
+        self.bind();
+        orphan_buffer(gl::ELEMENT_ARRAY_BUFFER, self.capacity, self.usage);
+    }
+
+    // Replaces the whole contents, the index count and type, growing the storage when
+    // needed.
+    pub fn update<T: Index>(&mut self, indices: &[T]) {
+        self.bind();
+        let size = std::mem::size_of_val(indices);
+        if size > self.capacity {
+            self.capacity = size;
+        }
+        orphan_buffer(gl::ELEMENT_ARRAY_BUFFER, self.capacity, self.usage);
+        upload_buffer_sub_data(gl::ELEMENT_ARRAY_BUFFER, 0, self.capacity, indices);
+        self.count = indices.len();
+        self.index_type = T::GL_TYPE;
+    }
 }
 
 impl Drop for IndexBuffer {
@@ -145,3 +439,409 @@ impl Drop for IndexBuffer {
         }
     }
 }
+
+// Persistently mapped buffer split into regions used round-robin, so the CPU can write
+// the next region while the GPU still reads the previous ones. Each region is guarded by
+// a fence placed when the CPU moves on to the next one. Needs OpenGL 4.4 or
+// GL_ARB_buffer_storage, see `is_supported`.
+pub struct StreamBuffer {
+    id: gl::types::GLuint,
+    target: gl::types::GLenum,
+    mapping: *mut u8,
+    region_size: usize,
+    fences: Vec<gl::types::GLsync>,
+    current_region: usize,
+}
+
+impl StreamBuffer {
+    const REGION_COUNT: usize = 3;
+    const FENCE_TIMEOUT_NS: u64 = 1_000_000_000;
+    // Waits of FENCE_TIMEOUT_NS before a region still used by the GPU is overwritten.
+    const MAX_FENCE_WAITS: u32 = 5;
+
+    pub fn is_supported() -> bool {
+        gl_supports((4, 4), "GL_ARB_buffer_storage")
+    }
+
+    pub fn new(target: gl::types::GLenum, region_size: usize) -> Result<Self, String> {
+        if !Self::is_supported() {
+            return Err("Stream buffers need OpenGL 4.4 or GL_ARB_buffer_storage".to_string());
+        }
+        let total_size = region_size * Self::REGION_COUNT;
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+        let mut id = 0;
+        let mapping;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            gl::BindBuffer(target, id);
+            gl::BufferStorage(
+                target,
+                total_size.try_into().unwrap(),
+                std::ptr::null(),
+                flags,
+            );
+            mapping =
+                gl::MapBufferRange(target, 0, total_size.try_into().unwrap(), flags) as *mut u8;
+        }
+        if mapping.is_null() {
+            unsafe {
+                gl::DeleteBuffers(1, &id);
+            }
+            return Err(format!("Cannot map stream buffer {}", id));
+        }
+        Ok(Self {
+            id,
+            target,
+            mapping,
+            region_size,
+            fences: vec![std::ptr::null(); Self::REGION_COUNT],
+            current_region: Self::REGION_COUNT - 1,
+        })
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindBuffer(self.target, self.id);
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn region_size(&self) -> usize {
+        self.region_size
+    }
+
+    // Fences the region written last, waits for the GPU to release the next one and
+    // copies `data` into it. Returns the byte offset of the data within the buffer, to be
+    // used as the attribute, index or draw offset.
+    pub fn write<T: Copy>(&mut self, data: &[T]) -> usize {
+        let size = std::mem::size_of_val(data);
+        assert!(
+            size <= self.region_size,
+            "stream buffer write of {} bytes exceeds region size of {} bytes",
+            size,
+            self.region_size
+        );
+
+        unsafe {
+            self.fences[self.current_region] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        }
+        self.current_region = (self.current_region + 1) % Self::REGION_COUNT;
+        self.wait_for_region(self.current_region);
+
+        let offset = self.current_region * self.region_size;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                self.mapping.add(offset),
+                size,
+            );
+        }
+        offset
+    }
+
+    fn wait_for_region(&mut self, region: usize) {
+        let fence = std::mem::replace(&mut self.fences[region], std::ptr::null());
+        if fence.is_null() {
+            return;
+        }
+        let mut waits = 0;
+        loop {
+            match unsafe {
+                gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, Self::FENCE_TIMEOUT_NS)
+            } {
+                gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => break,
+                gl::WAIT_FAILED => {
+                    println!("warning: waiting for stream buffer {} failed", self.id);
+                    break;
+                }
+                _ => {
+                    waits += 1;
+                    if waits == Self::MAX_FENCE_WAITS {
+                        println!(
+                            "warning: stream buffer {} region {} still in use by the GPU after {} waits, overwriting it",
+                            self.id, region, waits
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+        unsafe {
+            gl::DeleteSync(fence);
+        }
+    }
+}
+
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            for fence in &self.fences {
+                if !fence.is_null() {
+                    gl::DeleteSync(*fence);
+                }
+            }
+            self.bind();
+            gl::UnmapBuffer(self.target);
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
+
+// Describes a `#[repr(C)]` struct mirrored by a std140 uniform block in GLSL. The
+// member offsets are checked against the ones reported by the driver whenever a
+// shader declaring the block is linked.
+pub struct UniformBlockLayout {
+    pub name: &'static str,
+    pub binding: u32,
+    pub size: usize,
+    pub members: &'static [(&'static str, usize)],
+}
+
+pub trait UniformBlock: Copy {
+    const LAYOUT: UniformBlockLayout;
+}
+
+pub struct UniformBuffer<T: UniformBlock> {
+    id: gl::types::GLuint,
+    _block: PhantomData<T>,
+}
+
+impl<T: UniformBlock> UniformBuffer<T> {
+    pub fn new() -> Self {
+        assert_eq!(
+            T::LAYOUT.size,
+            std::mem::size_of::<T>(),
+            "uniform block {} layout does not match its struct size",
+            T::LAYOUT.name
+        );
+        let mut ub = Self {
+            id: 0,
+            _block: PhantomData,
+        };
+        unsafe {
+            gl::GenBuffers(1, &mut ub.id);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, ub.id);
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                std::mem::size_of::<T>().try_into().unwrap(),
+                std::ptr::null(),
+                gl::DYNAMIC_DRAW,
+            );
+        }
+        ub
+    }
+
+    pub fn update(&self, data: &T) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                std::mem::size_of::<T>().try_into().unwrap(),
+                data as *const T as *const std::ffi::c_void,
+            );
+        }
+    }
+
+    // Makes this buffer the source of the block for every shader linked against it.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, T::LAYOUT.binding, self.id);
+        }
+    }
+}
+
+impl<T: UniformBlock> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
+
+pub struct Renderbuffer {
+    id: gl::types::GLuint,
+}
+
+impl Renderbuffer {
+    pub fn new(internal_format: gl::types::GLenum, width: i32, height: i32) -> Self {
+        let mut rb = Self { id: 0 };
+        unsafe {
+            gl::GenRenderbuffers(1, &mut rb.id);
+        }
+        rb.set_storage(internal_format, width, height);
+        rb
+    }
+
+    pub fn set_storage(&self, internal_format: gl::types::GLenum, width: i32, height: i32) {
+        self.bind();
+        unsafe {
+            gl::RenderbufferStorage(gl::RENDERBUFFER, internal_format, width, height);
+        }
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.id);
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.id);
+        }
+    }
+}
+
+pub struct Framebuffer {
+    id: gl::types::GLuint,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        let mut fb = Self { id: 0 };
+        unsafe {
+            gl::GenFramebuffers(1, &mut fb.id);
+        }
+        fb
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    // Binds the framebuffer and sets the viewport until the returned guard is dropped,
+    // then restores the previously bound framebuffer and viewport.
+    pub fn bind_scoped(&self, viewport_size: (i32, i32)) -> FramebufferBinding<'_> {
+        let mut previous_id = 0;
+        let mut previous_viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_id);
+            gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+        }
+        self.bind();
+        unsafe {
+            gl::Viewport(0, 0, viewport_size.0, viewport_size.1);
+        }
+        FramebufferBinding {
+            _framebuffer: self,
+            previous_id: previous_id as gl::types::GLuint,
+            previous_viewport,
+        }
+    }
+
+    // Runs `f` with the framebuffer bound and restores the previous binding, so setting
+    // up attachments does not redirect the draws of the caller.
+    fn with_bound<R>(&self, f: impl FnOnce() -> R) -> R {
+        let mut previous_id = 0;
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_id);
+        }
+        self.bind();
+        let res = f();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_id as gl::types::GLuint);
+        }
+        res
+    }
+
+    pub fn attach_renderbuffer(&self, attachment: gl::types::GLenum, renderbuffer: &Renderbuffer) {
+        self.with_bound(|| unsafe {
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::RENDERBUFFER,
+                renderbuffer.id(),
+            );
+        });
+    }
+
+    pub fn attach_texture_2d(&self, attachment: gl::types::GLenum, texture: &Texture2D, mip: i32) {
+        self.with_bound(|| unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::TEXTURE_2D,
+                texture.id(),
+                mip,
+            );
+        });
+    }
+
+    pub fn attach_cube_map_face(
+        &self,
+        attachment: gl::types::GLenum,
+        texture: &TextureCubeMap,
+        face: u32,
+        mip: i32,
+    ) {
+        self.with_bound(|| unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                texture.id(),
+                mip,
+            );
+        });
+    }
+
+    pub fn check_status(&self) -> Result<(), String> {
+        let status = self.with_bound(|| unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) });
+        let reason = match status {
+            gl::FRAMEBUFFER_COMPLETE => return Ok(()),
+            gl::FRAMEBUFFER_UNDEFINED => "undefined",
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "incomplete attachment",
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "missing attachment",
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "incomplete draw buffer",
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "incomplete read buffer",
+            gl::FRAMEBUFFER_UNSUPPORTED => "unsupported",
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "incomplete multisample",
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "incomplete layer targets",
+            _ => "unknown status",
+        };
+        Err(format!(
+            "Framebuffer {} is not complete: {} (0x{:x})",
+            self.id, reason, status
+        ))
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
+
+pub struct FramebufferBinding<'a> {
+    _framebuffer: &'a Framebuffer,
+    previous_id: gl::types::GLuint,
+    previous_viewport: [i32; 4],
+}
+
+impl<'a> Drop for FramebufferBinding<'a> {
+    fn drop(&mut self) {
+        let [x, y, width, height] = self.previous_viewport;
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_id);
+            gl::Viewport(x, y, width, height);
+        }
+    }
+}
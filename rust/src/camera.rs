@@ -1,6 +1,10 @@
 extern crate cgmath;
 
 use cgmath::*;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::buffers::*;
+use crate::frustum::*;
 
 pub struct Camera {
     pub perspective: PerspectiveFov<f32>,
@@ -9,6 +13,24 @@ pub struct Camera {
     pub vertical_angle: Rad<f32>,
 }
 
+// Per-frame camera data, mirrors the Camera block in shaders/camera.glsl.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CameraBlock {
+    pub projection: Matrix4<f32>,
+    pub view: Matrix4<f32>,
+    pub position: Vector4<f32>,
+}
+
+impl UniformBlock for CameraBlock {
+    const LAYOUT: UniformBlockLayout = UniformBlockLayout {
+        name: "Camera",
+        binding: 0,
+        size: std::mem::size_of::<CameraBlock>(),
+        members: &[("projection", 0), ("view", 64), ("camera_position", 128)],
+    };
+}
+
 impl Camera {
     pub fn new_default(width: f32, height: f32) -> Self {
         Self::new_default_aspect(width / height)
@@ -18,7 +40,7 @@ impl Camera {
         Self {
             perspective: PerspectiveFov {
                 fovy: Rad::from(Deg(45.0)),
-                aspect: aspect,
+                aspect,
                 near: 0.1,
                 far: 100.0,
             },
@@ -27,7 +49,7 @@ impl Camera {
                 y: 0.0,
                 z: 0.5,
             },
-            horizontal_angle: Rad(3.14),
+            horizontal_angle: Rad(PI),
             vertical_angle: Rad(0.0),
         }
     }
@@ -42,9 +64,9 @@ impl Camera {
 
     pub fn right(&self) -> Vector3<f32> {
         Vector3 {
-            x: (self.horizontal_angle - Rad(3.14 / 2.0)).sin(),
+            x: (self.horizontal_angle - Rad(FRAC_PI_2)).sin(),
             y: 0.0,
-            z: (self.horizontal_angle - Rad(3.14 / 2.0)).cos(),
+            z: (self.horizontal_angle - Rad(FRAC_PI_2)).cos(),
         }
     }
 
@@ -67,4 +89,18 @@ impl Camera {
 
         (view, projection)
     }
+
+    pub fn frustum(&self) -> Frustum {
+        let (view, projection) = self.to_vp();
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    pub fn to_block(&self) -> CameraBlock {
+        let (view, projection) = self.to_vp();
+        CameraBlock {
+            projection,
+            view,
+            position: self.position.to_homogeneous(),
+        }
+    }
 }
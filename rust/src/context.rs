@@ -0,0 +1,30 @@
+extern crate gl;
+
+use std::ffi::CStr;
+
+// Version of the current context, queried once it is made current.
+pub fn gl_version() -> (i32, i32) {
+    let mut version = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut version.0);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut version.1);
+    }
+    version
+}
+
+pub fn gl_has_extension(name: &str) -> bool {
+    let mut count = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+    (0..count.max(0) as u32).any(|i| unsafe {
+        let extension = gl::GetStringi(gl::EXTENSIONS, i);
+        !extension.is_null() && CStr::from_ptr(extension as *const _).to_bytes() == name.as_bytes()
+    })
+}
+
+// Whether a feature beyond the required OpenGL version is available, either from the
+// core version that introduced it or from its extension.
+pub fn gl_supports(version: (i32, i32), extension: &str) -> bool {
+    gl_version() >= version || gl_has_extension(extension)
+}
@@ -0,0 +1,250 @@
+extern crate cgmath;
+
+use cgmath::*;
+
+use crate::mesh::*;
+
+// Points with dot(normal, p) + distance >= 0 are on the inner side.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let length = coefficients.truncate().magnitude();
+        Self {
+            normal: coefficients.truncate() / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    // Left, right, bottom, top, near and far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Extracts the clip planes of a projection * view matrix (Gribb & Hartmann), the
+    // planes are in world space.
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let row = |i| matrix.row(i);
+        Self {
+            planes: [
+                Plane::from_coefficients(row(3) + row(0)),
+                Plane::from_coefficients(row(3) - row(0)),
+                Plane::from_coefficients(row(3) + row(1)),
+                Plane::from_coefficients(row(3) - row(1)),
+                Plane::from_coefficients(row(3) + row(2)),
+                Plane::from_coefficients(row(3) - row(2)),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Only rejects boxes fully outside of one of the planes, so boxes near the corners
+    // of the frustum may still pass.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let farthest = vec3(
+                if plane.normal.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.normal.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.normal.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.signed_distance(farthest) >= 0.0
+        })
+    }
+
+    // Tests object space bounds placed with `transform`, the sphere first since it is
+    // cheaper.
+    pub fn is_visible(
+        &self,
+        bounds: &Aabb,
+        sphere: &BoundingSphere,
+        transform: &Matrix4<f32>,
+    ) -> bool {
+        self.intersects_sphere(&sphere.transform(transform))
+            && self.intersects_aabb(&bounds.transform(transform))
+    }
+}
+
+// How many objects a draw skipped, shown next to the frame time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+impl CullStats {
+    pub fn record(&mut self, visible: bool) -> bool {
+        if visible {
+            self.visible += 1;
+        } else {
+            self.culled += 1;
+        }
+        visible
+    }
+
+    pub fn total(&self) -> usize {
+        self.visible + self.culled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::*;
+
+    // Camera at the origin looking down -z with a 45 degree vertical field of view, so
+    // at depth d the side planes are d * tan(22.5) away from the axis.
+    fn camera_frustum() -> Frustum {
+        let mut cam = Camera::new_default_aspect(1.0);
+        cam.position = Point3::origin();
+        let (view, projection) = cam.to_vp();
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    fn sphere(center: Vector3<f32>, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    fn aabb(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    #[test]
+    fn planes_face_inwards() {
+        let frustum = camera_frustum();
+        for plane in frustum.planes.iter() {
+            assert!((plane.normal.magnitude() - 1.0).abs() < 1e-5);
+            assert!(plane.signed_distance(vec3(0.0, 0.0, -10.0)) > 0.0);
+        }
+        // Near and far planes sit at the camera's clip distances.
+        assert!((frustum.planes[4].signed_distance(vec3(0.0, 0.0, -0.1))).abs() < 1e-3);
+        assert!((frustum.planes[5].signed_distance(vec3(0.0, 0.0, -100.0))).abs() < 1e-2);
+    }
+
+    #[test]
+    fn spheres() {
+        let frustum = camera_frustum();
+        let half_width = 10.0 * (22.5f32).to_radians().tan();
+        let cases = [
+            // Inside.
+            (sphere(vec3(0.0, 0.0, -10.0), 1.0), true),
+            (sphere(vec3(0.0, 0.0, -99.0), 0.5), true),
+            // Behind the camera.
+            (sphere(vec3(0.0, 0.0, 10.0), 1.0), false),
+            (sphere(vec3(0.0, 0.0, 1.0), 0.5), false),
+            // Beyond the far plane.
+            (sphere(vec3(0.0, 0.0, -200.0), 1.0), false),
+            // Outside of a side plane.
+            (sphere(vec3(-50.0, 0.0, -10.0), 1.0), false),
+            (sphere(vec3(0.0, 50.0, -10.0), 1.0), false),
+            // Centers outside of the left plane, straddling it or not depending on the
+            // radius.
+            (sphere(vec3(-half_width - 0.5, 0.0, -10.0), 1.0), true),
+            (sphere(vec3(-half_width - 0.5, 0.0, -10.0), 0.3), false),
+            // Around the camera, straddling the near plane.
+            (sphere(vec3(0.0, 0.0, 0.0), 1.0), true),
+        ];
+        for (sphere, expected) in cases.iter() {
+            assert_eq!(frustum.intersects_sphere(sphere), *expected, "{:?}", sphere);
+        }
+    }
+
+    #[test]
+    fn boxes() {
+        let frustum = camera_frustum();
+        let cases = [
+            // Inside.
+            (aabb(vec3(-1.0, -1.0, -11.0), vec3(1.0, 1.0, -9.0)), true),
+            // Behind the camera.
+            (aabb(vec3(-1.0, -1.0, 1.0), vec3(1.0, 1.0, 2.0)), false),
+            // Straddling the near plane.
+            (aabb(vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0)), true),
+            // Straddling the right plane.
+            (aabb(vec3(4.0, -1.0, -11.0), vec3(6.0, 1.0, -9.0)), true),
+            // Outside of the right and the top planes.
+            (aabb(vec3(10.0, -1.0, -11.0), vec3(12.0, 1.0, -9.0)), false),
+            (aabb(vec3(-1.0, 10.0, -11.0), vec3(1.0, 12.0, -9.0)), false),
+            // Crossing the whole frustum.
+            (
+                aabb(vec3(-100.0, -0.1, -11.0), vec3(100.0, 0.1, -9.0)),
+                true,
+            ),
+        ];
+        for (aabb, expected) in cases.iter() {
+            assert_eq!(frustum.intersects_aabb(aabb), *expected, "{:?}", aabb);
+        }
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let frustum = camera_frustum();
+        let bounds = aabb(vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0));
+        let sphere = sphere(Vector3::zero(), 3.0f32.sqrt());
+        let cases = [
+            (Matrix4::from_translation(vec3(0.0, 0.0, -10.0)), true),
+            (Matrix4::from_translation(vec3(0.0, 0.0, 10.0)), false),
+            (Matrix4::from_translation(vec3(-20.0, 0.0, -10.0)), false),
+            // Scaled up enough to reach into the frustum.
+            (
+                Matrix4::from_translation(vec3(-20.0, 0.0, -10.0)) * Matrix4::from_scale(16.0),
+                true,
+            ),
+            // Rotated half a turn around y, still in front of the camera.
+            (
+                Matrix4::from_translation(vec3(0.0, 0.0, -5.0)) * Matrix4::from_angle_y(Deg(180.0)),
+                true,
+            ),
+        ];
+        for (transform, expected) in cases.iter() {
+            assert_eq!(
+                frustum.is_visible(&bounds, &sphere, transform),
+                *expected,
+                "{:?}",
+                transform
+            );
+        }
+    }
+
+    #[test]
+    fn cull_stats() {
+        let mut stats = CullStats::default();
+        assert!(stats.record(true));
+        assert!(!stats.record(false));
+        assert!(!stats.record(false));
+        assert_eq!(
+            stats,
+            CullStats {
+                visible: 1,
+                culled: 2
+            }
+        );
+        assert_eq!(stats.total(), 3);
+    }
+}
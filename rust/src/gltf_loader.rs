@@ -0,0 +1,738 @@
+extern crate cgmath;
+extern crate gl;
+extern crate gltf;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cgmath::*;
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Format;
+use gltf::mesh::util::ReadIndices;
+use gltf::mesh::Mode;
+
+use crate::animation::*;
+use crate::buffers::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::shaders::*;
+use crate::tangents::*;
+use crate::textures::*;
+
+// Attribute locations shared with the mesh shaders.
+pub const POSITION_LOCATION: u32 = 0;
+pub const TEXCOORD_LOCATION: u32 = 1;
+pub const NORMAL_LOCATION: u32 = 2;
+pub const TANGENT_LOCATION: u32 = 3;
+pub const JOINTS_LOCATION: u32 = 9;
+pub const WEIGHTS_LOCATION: u32 = 10;
+// Second UV set, materials pick theirs per texture.
+pub const TEXCOORD1_LOCATION: u32 = 11;
+
+// Has to match MAX_MORPH_TARGETS in shaders/morph.glsl.
+pub const MAX_MORPH_TARGETS: usize = 8;
+// Follows the material textures.
+pub const MORPH_TARGET_SLOT: u32 = Material::FIRST_TEXTURE_SLOT + 5;
+
+pub struct GltfPrimitive {
+    vertex_array: VertexArray,
+    _vertex_buffers: Vec<VertexBuffer>,
+    index_buffer: Option<IndexBuffer>,
+    vertex_count: usize,
+    mode: gl::types::GLenum,
+    morph_targets: Option<BufferTexture>,
+    morph_target_count: usize,
+    bounds: Aabb,
+    bounding_sphere: BoundingSphere,
+    pub material: Option<usize>,
+}
+
+impl GltfPrimitive {
+    fn load(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Result<Self, String> {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions = reader
+            .read_positions()
+            .ok_or("Primitive has no POSITION attribute")?
+            .collect::<Vec<_>>();
+
+        let vertex_array = VertexArray::new();
+        let mut vertex_buffers = vec![attach_attribute(
+            &vertex_array,
+            &VertexLayout::new().float(POSITION_LOCATION, 3),
+            &positions,
+        )];
+        let tex_coords = reader
+            .read_tex_coords(0)
+            .map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+        if let Some(tex_coords) = &tex_coords {
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().float(TEXCOORD_LOCATION, 2),
+                tex_coords,
+            ));
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(1) {
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().float(TEXCOORD1_LOCATION, 2),
+                &tex_coords.into_f32().collect::<Vec<_>>(),
+            ));
+        }
+        let normals = reader
+            .read_normals()
+            .map(|normals| normals.collect::<Vec<_>>());
+        if let Some(normals) = &normals {
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().float(NORMAL_LOCATION, 3),
+                normals,
+            ));
+        }
+        let tangents = match (reader.read_tangents(), &normals, &tex_coords) {
+            (Some(tangents), _, _) => Some(tangents.collect::<Vec<_>>()),
+            // glTF leaves missing tangents to the client, computed with MikkTSpace.
+            (None, Some(normals), Some(tex_coords)) => {
+                primitive_triangles(primitive.mode(), &reader, positions.len())
+                    .map(|triangles| generate_tangents(&positions, normals, tex_coords, &triangles))
+            }
+            _ => None,
+        };
+        if let Some(tangents) = &tangents {
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().float(TANGENT_LOCATION, 4),
+                tangents,
+            ));
+        }
+        if let Some(joints) = reader.read_joints(0) {
+            let joints = joints.into_u16().collect::<Vec<_>>();
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().integer(JOINTS_LOCATION, 4, gl::UNSIGNED_SHORT),
+                &joints,
+            ));
+        }
+        if let Some(weights) = reader.read_weights(0) {
+            let weights = weights.into_f32().collect::<Vec<_>>();
+            vertex_buffers.push(attach_attribute(
+                &vertex_array,
+                &VertexLayout::new().float(WEIGHTS_LOCATION, 4),
+                &weights,
+            ));
+        }
+
+        let index_buffer = reader.read_indices().map(|indices| match indices {
+            ReadIndices::U8(indices) => IndexBuffer::new_static(&indices.collect::<Vec<_>>()),
+            ReadIndices::U16(indices) => IndexBuffer::new_static(&indices.collect::<Vec<_>>()),
+            ReadIndices::U32(indices) => IndexBuffer::new_static(&indices.collect::<Vec<_>>()),
+        });
+
+        let morph_target_count = primitive.morph_targets().count();
+        if morph_target_count > MAX_MORPH_TARGETS {
+            return Err(format!(
+                "Primitive has {} morph targets, at most {} are supported",
+                morph_target_count, MAX_MORPH_TARGETS
+            ));
+        }
+        let morph_targets = if morph_target_count > 0 {
+            Some(load_morph_targets(
+                &reader,
+                positions.len(),
+                morph_target_count,
+            ))
+        } else {
+            None
+        };
+
+        let points = positions.iter().map(|position| Vector3::from(*position));
+        Ok(Self {
+            vertex_array,
+            _vertex_buffers: vertex_buffers,
+            index_buffer,
+            vertex_count: positions.len(),
+            mode: primitive.mode().as_gl_enum(),
+            morph_targets,
+            morph_target_count,
+            bounds: Aabb::from_points(points.clone()),
+            bounding_sphere: BoundingSphere::from_points(points),
+            material: primitive.material().index(),
+        })
+    }
+
+    // Sets the uniforms of shaders/morph.glsl. Primitives without targets disable
+    // morphing, so it has to be called for every primitive once a shader uses it.
+    pub fn bind_morph_targets(&self, shader: &Shader, weights: &[f32]) {
+        match &self.morph_targets {
+            Some(morph_targets) => {
+                let count = self.morph_target_count.min(weights.len());
+                morph_targets.set_slot(&MORPH_TARGET_SLOT);
+                shader.set_uniform_1i("morph_targets", &(MORPH_TARGET_SLOT as i32));
+                shader.set_uniform_1i("morph_target_count", &(count as i32));
+                shader.set_uniform_1fv("morph_weights", &weights[..count]);
+            }
+            None => shader.set_uniform_1i("morph_target_count", &0),
+        }
+    }
+
+    // Bounds of the rest pose. Morph targets can move vertices outside of them.
+    pub fn bounds(&self) -> (&Aabb, &BoundingSphere) {
+        (&self.bounds, &self.bounding_sphere)
+    }
+
+    pub fn has_morph_targets(&self) -> bool {
+        self.morph_targets.is_some()
+    }
+
+    pub fn draw(&self) {
+        self.vertex_array.bind();
+        match &self.index_buffer {
+            Some(index_buffer) => {
+                index_buffer.bind();
+                unsafe {
+                    gl::DrawElements(
+                        self.mode,
+                        index_buffer.count() as i32,
+                        index_buffer.index_type(),
+                        std::ptr::null(),
+                    );
+                }
+            }
+            None => unsafe {
+                gl::DrawArrays(self.mode, 0, self.vertex_count as i32);
+            },
+        }
+    }
+}
+
+// Triangles of a primitive, None for the point and line modes.
+fn primitive_triangles<'a, 's, F>(
+    mode: Mode,
+    reader: &gltf::mesh::Reader<'a, 's, F>,
+    vertex_count: usize,
+) -> Option<Vec<[u32; 3]>>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertex_count as u32).collect(),
+    };
+    match mode {
+        Mode::Triangles => Some(triangle_list(&indices)),
+        Mode::TriangleStrip => Some(triangle_strip(&indices)),
+        Mode::TriangleFan => Some(triangle_fan(&indices)),
+        _ => None,
+    }
+}
+
+// Deltas are stored vertex major with three texels per vertex and target, holding the
+// position, normal and tangent delta. Attributes a target leaves out stay zero.
+fn load_morph_targets<'a, 's, F>(
+    reader: &gltf::mesh::Reader<'a, 's, F>,
+    vertex_count: usize,
+    target_count: usize,
+) -> BufferTexture
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let mut deltas = vec![[0.0f32; 4]; vertex_count * target_count * 3];
+    for (target, (positions, normals, tangents)) in reader.read_morph_targets().enumerate() {
+        for (attribute, values) in vec![positions, normals, tangents].into_iter().enumerate() {
+            if let Some(values) = values {
+                for (vertex, value) in values.take(vertex_count).enumerate() {
+                    let texel = (vertex * target_count + target) * 3 + attribute;
+                    deltas[texel] = [value[0], value[1], value[2], 0.0];
+                }
+            }
+        }
+    }
+    BufferTexture::new(gl::RGBA32F, &deltas)
+}
+
+// Every accessor gets its own buffer, so primitives missing an attribute simply leave
+// the location disabled.
+fn attach_attribute<T: Copy>(
+    vertex_array: &VertexArray,
+    layout: &VertexLayout,
+    data: &[T],
+) -> VertexBuffer {
+    vertex_array.bind();
+    let vb = VertexBuffer::new_static(data);
+    vb.bind();
+    vertex_array.set_layout(layout);
+    vb
+}
+
+// Textures shared between materials, keyed by glTF texture index and sRGB decoding.
+type TextureCache = HashMap<(usize, bool), Rc<Texture2D>>;
+
+fn load_texture(
+    texture: &gltf::Texture,
+    images: &[gltf::image::Data],
+    srgb: bool,
+    cache: &mut TextureCache,
+) -> Result<Rc<Texture2D>, String> {
+    if let Some(loaded) = cache.get(&(texture.index(), srgb)) {
+        return Ok(loaded.clone());
+    }
+
+    let image = &images[texture.source().index()];
+    let (internal_format, format) = match image.format {
+        Format::R8 => (gl::R8, gl::RED),
+        Format::R8G8 => (gl::RG8, gl::RG),
+        Format::R8G8B8 if srgb => (gl::SRGB8, gl::RGB),
+        Format::R8G8B8 => (gl::RGB8, gl::RGB),
+        Format::R8G8B8A8 if srgb => (gl::SRGB8_ALPHA8, gl::RGBA),
+        Format::R8G8B8A8 => (gl::RGBA8, gl::RGBA),
+        _ => {
+            return Err(format!(
+                "Unsupported format {:?} of image {}",
+                image.format,
+                texture.source().index()
+            ))
+        }
+    };
+    let loaded = Texture2D::new_from_pixels(
+        internal_format,
+        image.width,
+        image.height,
+        format,
+        &image.pixels,
+    );
+
+    let sampler = texture.sampler();
+    loaded.set_sampler(
+        sampler.wrap_s().as_gl_enum(),
+        sampler.wrap_t().as_gl_enum(),
+        sampler
+            .mag_filter()
+            .map_or(gl::LINEAR, |filter| filter.as_gl_enum()),
+        sampler
+            .min_filter()
+            .map_or(gl::LINEAR_MIPMAP_LINEAR, |filter| filter.as_gl_enum()),
+    );
+
+    let loaded = Rc::new(loaded);
+    cache.insert((texture.index(), srgb), loaded.clone());
+    Ok(loaded)
+}
+
+const TEXTURE_TRANSFORM_EXTENSION: &str = "KHR_texture_transform";
+
+// Reads a KHR_texture_transform extension object. Its texCoord, when present, overrides
+// the one of the texture info it is attached to.
+fn parse_texture_transform(
+    value: &gltf::json::Value,
+) -> Result<(TextureTransform, Option<u32>), String> {
+    let vector = |key: &str, default: Vector2<f32>| match value.get(key) {
+        None => Ok(default),
+        Some(array) => match array.as_array().map(|array| array.as_slice()) {
+            Some([x, y]) => match (x.as_f64(), y.as_f64()) {
+                (Some(x), Some(y)) => Ok(vec2(x as f32, y as f32)),
+                _ => Err(format!("KHR_texture_transform {} is not numeric", key)),
+            },
+            _ => Err(format!("KHR_texture_transform {} is not a 2D vector", key)),
+        },
+    };
+    let rotation = match value.get("rotation") {
+        None => 0.0,
+        Some(rotation) => rotation
+            .as_f64()
+            .ok_or("KHR_texture_transform rotation is not numeric")?
+            as f32,
+    };
+    let tex_coord = match value.get("texCoord") {
+        None => None,
+        Some(tex_coord) => Some(
+            tex_coord
+                .as_u64()
+                .ok_or("KHR_texture_transform texCoord is not an index")? as u32,
+        ),
+    };
+    let transform = TextureTransform {
+        offset: vector("offset", vec2(0.0, 0.0))?,
+        rotation,
+        scale: vector("scale", vec2(1.0, 1.0))?,
+    };
+    Ok((transform, tex_coord))
+}
+
+// Shared by the texture info kinds, which only differ in their extra factor.
+fn load_material_texture(
+    texture: &gltf::Texture,
+    tex_coord: u32,
+    transform: Option<&gltf::json::Value>,
+    images: &[gltf::image::Data],
+    srgb: bool,
+    cache: &mut TextureCache,
+) -> Result<MaterialTexture, String> {
+    let (transform, tex_coord) = match transform {
+        Some(value) => {
+            let (transform, override_tex_coord) = parse_texture_transform(value)?;
+            (transform, override_tex_coord.unwrap_or(tex_coord))
+        }
+        None => (TextureTransform::identity(), tex_coord),
+    };
+    if tex_coord > 1 {
+        return Err(format!(
+            "Texture {} uses TEXCOORD_{}, only sets 0 and 1 are supported",
+            texture.index(),
+            tex_coord
+        ));
+    }
+    Ok(MaterialTexture {
+        texture: load_texture(texture, images, srgb, cache)?,
+        transform,
+        tex_coord,
+    })
+}
+
+fn load_texture_info(
+    info: &gltf::texture::Info,
+    images: &[gltf::image::Data],
+    srgb: bool,
+    cache: &mut TextureCache,
+) -> Result<MaterialTexture, String> {
+    load_material_texture(
+        &info.texture(),
+        info.tex_coord(),
+        info.extension_value(TEXTURE_TRANSFORM_EXTENSION),
+        images,
+        srgb,
+        cache,
+    )
+}
+
+fn load_material(
+    material: &gltf::Material,
+    images: &[gltf::image::Data],
+    cache: &mut TextureCache,
+) -> Result<Material, String> {
+    let pbr = material.pbr_metallic_roughness();
+    let mut res = Material {
+        name: material.name().map(|name| name.to_string()),
+        base_color_factor: pbr.base_color_factor().into(),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        emissive_factor: material.emissive_factor().into(),
+        ..Material::default()
+    };
+
+    if let Some(info) = pbr.base_color_texture() {
+        res.base_color_texture = Some(load_texture_info(&info, images, true, cache)?);
+    }
+    if let Some(info) = pbr.metallic_roughness_texture() {
+        res.metallic_roughness_texture = Some(load_texture_info(&info, images, false, cache)?);
+    }
+    if let Some(info) = material.emissive_texture() {
+        res.emissive_texture = Some(load_texture_info(&info, images, true, cache)?);
+    }
+    if let Some(normal) = material.normal_texture() {
+        res.normal_scale = normal.scale();
+        res.normal_texture = Some(load_material_texture(
+            &normal.texture(),
+            normal.tex_coord(),
+            normal.extension_value(TEXTURE_TRANSFORM_EXTENSION),
+            images,
+            false,
+            cache,
+        )?);
+    }
+    if let Some(occlusion) = material.occlusion_texture() {
+        res.occlusion_strength = occlusion.strength();
+        res.occlusion_texture = Some(load_material_texture(
+            &occlusion.texture(),
+            occlusion.tex_coord(),
+            occlusion.extension_value(TEXTURE_TRANSFORM_EXTENSION),
+            images,
+            false,
+            cache,
+        )?);
+    }
+    Ok(res)
+}
+
+// glTF requires every primitive of a mesh to have the same number of morph targets.
+fn morph_target_count(mesh: &gltf::Mesh) -> usize {
+    mesh.primitives()
+        .next()
+        .map_or(0, |primitive| primitive.morph_targets().count())
+}
+
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+    pub morph_target_count: usize,
+}
+
+fn load_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Animation, String> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let times = reader
+            .read_inputs()
+            .ok_or("Animation channel has no input")?
+            .collect::<Vec<_>>();
+        let (property, components, values): (_, _, Vec<f32>) = match reader
+            .read_outputs()
+            .ok_or("Animation channel has no output")?
+        {
+            ReadOutputs::Translations(values) => (
+                AnimationProperty::Translation,
+                3,
+                values.flat_map(|value| value.to_vec()).collect(),
+            ),
+            ReadOutputs::Rotations(values) => (
+                AnimationProperty::Rotation,
+                4,
+                values.into_f32().flat_map(|value| value.to_vec()).collect(),
+            ),
+            ReadOutputs::Scales(values) => (
+                AnimationProperty::Scale,
+                3,
+                values.flat_map(|value| value.to_vec()).collect(),
+            ),
+            ReadOutputs::MorphTargetWeights(values) => (
+                AnimationProperty::Weights,
+                channel
+                    .target()
+                    .node()
+                    .mesh()
+                    .map_or(0, |mesh| morph_target_count(&mesh)),
+                values.into_f32().collect(),
+            ),
+        };
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        channels.push(AnimationChannel {
+            node: channel.target().node().index(),
+            property,
+            sampler: AnimationSampler::new(times, values, components, interpolation)?,
+        });
+    }
+    Ok(Animation {
+        name: animation.name().map(|name| name.to_string()),
+        channels,
+    })
+}
+
+pub struct GltfSkin {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+impl GltfSkin {
+    fn load(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Result<Self, String> {
+        let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+        if joints.len() > MAX_JOINTS {
+            return Err(format!(
+                "Skin has {} joints, at most {} are supported",
+                joints.len(),
+                MAX_JOINTS
+            ));
+        }
+
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).collect::<Vec<_>>(),
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+        if inverse_bind_matrices.len() < joints.len() {
+            return Err(format!(
+                "Skin has {} joints but only {} inverse bind matrices",
+                joints.len(),
+                inverse_bind_matrices.len()
+            ));
+        }
+
+        Ok(Self {
+            joints,
+            inverse_bind_matrices,
+        })
+    }
+
+    // Joint matrices relative to the skinned node, so the vertex shader can still
+    // apply the node's own model matrix afterwards.
+    pub fn to_block<F>(&self, node_transform: &Matrix4<f32>, world_transform: F) -> SkinBlock
+    where
+        F: Fn(usize) -> Matrix4<f32>,
+    {
+        let inverse_node_transform = node_transform.invert().unwrap_or(Matrix4::identity());
+        let mut block = SkinBlock {
+            joint_matrices: [Matrix4::identity(); MAX_JOINTS],
+        };
+        for (i, (joint, inverse_bind_matrix)) in self
+            .joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .enumerate()
+        {
+            block.joint_matrices[i] =
+                inverse_node_transform * world_transform(*joint) * inverse_bind_matrix;
+        }
+        block
+    }
+}
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+    // Morph target weights of the mesh, driven by animations or set directly.
+    pub weights: Vec<f32>,
+    pub children: Vec<usize>,
+}
+
+// Node weights override the mesh defaults, both are optional.
+fn node_weights(node: &gltf::Node) -> Vec<f32> {
+    let mesh = match node.mesh() {
+        Some(mesh) => mesh,
+        None => return Vec::new(),
+    };
+    match node.weights().or_else(|| mesh.weights()) {
+        Some(weights) => weights.to_vec(),
+        None => vec![0.0; morph_target_count(&mesh)],
+    }
+}
+
+// The contents of a glTF file, drawn once imported into a SceneGraph.
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<GltfNode>,
+    pub roots: Vec<usize>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<Animation>,
+}
+
+impl GltfScene {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let (document, buffers, images) =
+            gltf::import(path).map_err(|e| format!("Failed to load glTF {}: {}", path, e))?;
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| {
+                let primitives = mesh
+                    .primitives()
+                    .map(|primitive| GltfPrimitive::load(&primitive, &buffers))
+                    .collect::<Result<Vec<_>, String>>()
+                    .map_err(|msg| format!("{}: mesh {}: {}", path, mesh.index(), msg))?;
+                Ok(GltfMesh {
+                    name: mesh.name().map(|name| name.to_string()),
+                    primitives,
+                    morph_target_count: morph_target_count(&mesh),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut texture_cache = TextureCache::new();
+        let materials = document
+            .materials()
+            .map(|material| {
+                load_material(&material, &images, &mut texture_cache).map_err(|msg| {
+                    format!(
+                        "{}: material {}: {}",
+                        path,
+                        material.index().unwrap_or(0),
+                        msg
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                GltfNode {
+                    name: node.name().map(|name| name.to_string()),
+                    translation: translation.into(),
+                    rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+                    scale: scale.into(),
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    skin: node.skin().map(|skin| skin.index()),
+                    weights: node_weights(&node),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect();
+
+        let skins = document
+            .skins()
+            .map(|skin| {
+                GltfSkin::load(&skin, &buffers)
+                    .map_err(|msg| format!("{}: skin {}: {}", path, skin.index(), msg))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let animations = document
+            .animations()
+            .map(|animation| {
+                load_animation(&animation, &buffers)
+                    .map_err(|msg| format!("{}: animation {}: {}", path, animation.index(), msg))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| format!("glTF file has no scenes: {}", path))?;
+        let roots = scene.nodes().map(|node| node.index()).collect();
+
+        Ok(Self {
+            meshes,
+            materials,
+            nodes,
+            roots,
+            skins,
+            animations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<(TextureTransform, Option<u32>), String> {
+        parse_texture_transform(&gltf::json::deserialize::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn texture_transform_defaults() {
+        let (transform, tex_coord) = parse("{}").unwrap();
+        assert_eq!(transform.offset, vec2(0.0, 0.0));
+        assert_eq!(transform.rotation, 0.0);
+        assert_eq!(transform.scale, vec2(1.0, 1.0));
+        assert_eq!(tex_coord, None);
+    }
+
+    #[test]
+    fn texture_transform_fields() {
+        let (transform, tex_coord) =
+            parse(r#"{"offset": [0.5, 0.25], "rotation": 1.5, "scale": [2, 3], "texCoord": 1}"#)
+                .unwrap();
+        assert_eq!(transform.offset, vec2(0.5, 0.25));
+        assert_eq!(transform.rotation, 1.5);
+        assert_eq!(transform.scale, vec2(2.0, 3.0));
+        assert_eq!(tex_coord, Some(1));
+    }
+
+    #[test]
+    fn texture_transform_rejects_malformed_fields() {
+        assert!(parse(r#"{"offset": [0.5]}"#).is_err());
+        assert!(parse(r#"{"scale": ["a", 1]}"#).is_err());
+        assert!(parse(r#"{"rotation": "half"}"#).is_err());
+        assert!(parse(r#"{"texCoord": -1}"#).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+extern crate image;
+
+use std::path::{Path, PathBuf};
+
+use image::*;
+
+use crate::headless::*;
+use crate::screenshot::*;
+use crate::test_scenes::*;
+
+pub const GOLDEN_DIRECTORY: &str = "../resources/golden";
+// The references are rendered after this many frames, whatever --frames says, since
+// animated scenes look different at every frame.
+pub const GOLDEN_FRAMES: u32 = 60;
+
+pub struct Tolerance {
+    // CIE76 colour distance above which a single pixel counts as different.
+    pub max_delta_e: f32,
+    // Fraction of differing pixels allowed before the comparison fails.
+    pub max_differing_fraction: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            max_delta_e: 5.0,
+            max_differing_fraction: 0.001,
+        }
+    }
+}
+
+pub struct Comparison {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_delta_e: f32,
+    pub diff_image: RgbImage,
+}
+
+impl Comparison {
+    pub fn differing_fraction(&self) -> f32 {
+        self.differing_pixels as f32 / self.total_pixels.max(1) as f32
+    }
+
+    pub fn passed(&self, tolerance: &Tolerance) -> bool {
+        self.differing_fraction() <= tolerance.max_differing_fraction
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn rgb_to_lab(pixel: &Rgb<u8>) -> [f32; 3] {
+    let r = srgb_to_linear(pixel[0]);
+    let g = srgb_to_linear(pixel[1]);
+    let b = srgb_to_linear(pixel[2]);
+
+    // sRGB to XYZ, normalized by the D65 white point.
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e(a: &Rgb<u8>, b: &Rgb<u8>) -> f32 {
+    let a = rgb_to_lab(a);
+    let b = rgb_to_lab(b);
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+pub fn compare_images(
+    reference: &RgbImage,
+    actual: &RgbImage,
+    tolerance: &Tolerance,
+) -> Result<Comparison, String> {
+    if reference.dimensions() != actual.dimensions() {
+        return Err(format!(
+            "Image size mismatch: reference {:?}, actual {:?}",
+            reference.dimensions(),
+            actual.dimensions()
+        ));
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_delta_e: f32 = 0.0;
+    let diff_image = ImageBuffer::from_fn(reference.width(), reference.height(), |x, y| {
+        let expected = reference.get_pixel(x, y);
+        let delta = delta_e(expected, actual.get_pixel(x, y));
+        max_delta_e = max_delta_e.max(delta);
+        if delta > tolerance.max_delta_e {
+            differing_pixels += 1;
+            let intensity = (128.0 + delta * 4.0).min(255.0) as u8;
+            Rgb([intensity, 0, 0])
+        } else {
+            let luma = expected.to_luma()[0] / 3;
+            Rgb([luma, luma, luma])
+        }
+    });
+
+    Ok(Comparison {
+        differing_pixels,
+        total_pixels: (reference.width() * reference.height()) as usize,
+        max_delta_e,
+        diff_image,
+    })
+}
+
+fn reference_path(scene_name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIRECTORY).join(format!("{}.png", scene_file_stem(scene_name)))
+}
+
+fn diff_path(scene_name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIRECTORY).join(format!("{}.diff.png", scene_file_stem(scene_name)))
+}
+
+fn check_scene(scene_name: &str, actual: &RgbImage, bless: bool) -> Result<(), String> {
+    let reference_path = reference_path(scene_name);
+    if bless {
+        std::fs::create_dir_all(GOLDEN_DIRECTORY)
+            .map_err(|_| format!("Cannot create directory: {}", GOLDEN_DIRECTORY))?;
+        actual
+            .save(&reference_path)
+            .map_err(|_| format!("Cannot write: {}", reference_path.display()))?;
+        println!("{}: reference updated", scene_name);
+        return Ok(());
+    }
+
+    let reference = open(&reference_path)
+        .map_err(|_| {
+            format!(
+                "Missing reference image: {}, rerun with --bless to create it",
+                reference_path.display()
+            )
+        })?
+        .to_rgb();
+
+    let tolerance = Tolerance::default();
+    let comparison = compare_images(&reference, actual, &tolerance)?;
+    let diff_path = diff_path(scene_name);
+    if comparison.passed(&tolerance) {
+        std::fs::remove_file(&diff_path).ok();
+        println!(
+            "{}: ok ({:.4}% pixels differ, max dE {:.2})",
+            scene_name,
+            comparison.differing_fraction() * 100.0,
+            comparison.max_delta_e
+        );
+        return Ok(());
+    }
+
+    comparison
+        .diff_image
+        .save(&diff_path)
+        .map_err(|_| format!("Cannot write: {}", diff_path.display()))?;
+    Err(format!(
+        "{}: {:.4}% pixels differ (max dE {:.2}), diff written to {}",
+        scene_name,
+        comparison.differing_fraction() * 100.0,
+        comparison.max_delta_e,
+        diff_path.display()
+    ))
+}
+
+pub fn run_golden(options: &HeadlessOptions) -> Result<(), String> {
+    let headless = HeadlessContext::new(options.size)?;
+    crate::load_gl(|s| headless.get_proc_address(s))?;
+
+    let target = OffscreenTarget::new(options.size)?;
+    target.bind();
+
+    let mut test_app = TestApp::new(options.size);
+    crate::register_scenes(&mut test_app);
+
+    if options.frames != GOLDEN_FRAMES {
+        println!(
+            "Ignoring --frames {}, golden images are rendered after {} frames",
+            options.frames, GOLDEN_FRAMES
+        );
+    }
+
+    let scene_names = match &options.scene {
+        Some(name) => vec![name.clone()],
+        None => test_app.scene_names(),
+    };
+
+    let mut failures = Vec::new();
+    for name in &scene_names {
+        // A scene which fails to load must not stop the others from being checked.
+        if let Err(msg) = test_app.select(name) {
+            println!("{}", msg);
+            failures.push(name.clone());
+            continue;
+        }
+        for _ in 0..GOLDEN_FRAMES {
+            target.bind();
+            test_app.update(HeadlessOptions::FRAME_TIME);
+            test_app.render();
+        }
+        let actual = read_pixels_rgb8(target.id(), options.size);
+        test_app.deselect();
+
+        if let Err(msg) = check_scene(name, &actual, options.bless) {
+            println!("{}", msg);
+            failures.push(name.clone());
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Golden image check failed for: {}",
+            failures.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_lab(actual: [f32; 3], expected: [f32; 3]) {
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 0.1,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn rgb_to_lab_reference_colours() {
+        assert_lab(rgb_to_lab(&Rgb([0, 0, 0])), [0.0, 0.0, 0.0]);
+        assert_lab(rgb_to_lab(&Rgb([255, 255, 255])), [100.0, 0.0, 0.0]);
+        assert_lab(rgb_to_lab(&Rgb([255, 0, 0])), [53.24, 80.09, 67.20]);
+    }
+
+    #[test]
+    fn delta_e_known_pairs() {
+        assert_eq!(delta_e(&Rgb([12, 34, 56]), &Rgb([12, 34, 56])), 0.0);
+        assert!((delta_e(&Rgb([0, 0, 0]), &Rgb([255, 255, 255])) - 100.0).abs() < 0.1);
+        assert!((delta_e(&Rgb([255, 0, 0]), &Rgb([0, 0, 0])) - 117.32).abs() < 0.1);
+    }
+
+    #[test]
+    fn identical_images_do_not_differ() {
+        let image = ImageBuffer::from_fn(8, 4, |x, y| Rgb([x as u8 * 30, y as u8 * 60, 128]));
+        let comparison = compare_images(&image, &image, &Tolerance::default()).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.total_pixels, 32);
+        assert_eq!(comparison.max_delta_e, 0.0);
+        assert!(comparison.passed(&Tolerance::default()));
+    }
+
+    #[test]
+    fn differing_pixels_are_counted() {
+        let reference = RgbImage::from_pixel(10, 10, Rgb([0, 0, 0]));
+        let mut actual = reference.clone();
+        actual.put_pixel(3, 7, Rgb([255, 255, 255]));
+        actual.put_pixel(4, 7, Rgb([1, 1, 1]));
+        let comparison = compare_images(&reference, &actual, &Tolerance::default()).unwrap();
+        assert_eq!(comparison.differing_pixels, 1);
+        assert!((comparison.max_delta_e - 100.0).abs() < 0.1);
+        assert!(!comparison.passed(&Tolerance::default()));
+    }
+
+    #[test]
+    fn size_mismatch_is_an_error() {
+        let reference = RgbImage::new(4, 4);
+        let actual = RgbImage::new(4, 5);
+        assert!(compare_images(&reference, &actual, &Tolerance::default()).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+extern crate gl;
+extern crate glutin;
+#[cfg(target_os = "linux")]
+extern crate glutin_egl_sys;
+#[cfg(target_os = "linux")]
+extern crate libloading;
+
+use std::ffi::{c_void, CString};
+use std::time::Duration;
+
+use glutin::*;
+#[cfg(target_os = "linux")]
+use glutin_egl_sys::egl;
+
+use crate::buffers::*;
+use crate::test_scenes::*;
+
+pub struct HeadlessOptions {
+    pub scene: Option<String>,
+    pub frames: u32,
+    pub size: (u32, u32),
+    pub golden: bool,
+    pub bless: bool,
+}
+
+impl HeadlessOptions {
+    const DEFAULT_FRAMES: u32 = 60;
+    const DEFAULT_SIZE: (u32, u32) = (1600, 900);
+
+    pub const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+    // Returns Ok(None) when neither --headless nor --golden is present, so the caller
+    // can fall back to the windowed mode.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut headless = false;
+        let mut scene = None;
+        let mut frames = Self::DEFAULT_FRAMES;
+        let mut size = Self::DEFAULT_SIZE;
+        let mut golden = false;
+        let mut bless = false;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    headless = true;
+                    scene = Some(
+                        iter.next()
+                            .ok_or("Missing scene name after --headless")?
+                            .clone(),
+                    );
+                }
+                "--scene" => {
+                    scene = Some(iter.next().ok_or("Missing value after --scene")?.clone());
+                }
+                "--golden" => golden = true,
+                "--bless" => bless = true,
+                "--frames" => {
+                    let value = iter.next().ok_or("Missing value after --frames")?;
+                    frames = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count: {}", value))?;
+                }
+                "--size" => {
+                    let value = iter.next().ok_or("Missing value after --size")?;
+                    size = parse_size(value)?;
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        if bless && !golden {
+            return Err("--bless can only be used together with --golden".to_string());
+        }
+        if !headless && !golden {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            scene,
+            frames,
+            size,
+            golden,
+            bless,
+        }))
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.split('x');
+    let mut next = || -> Option<u32> { parts.next()?.parse().ok() };
+    match (next(), next()) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Invalid size: {}, expected WIDTHxHEIGHT", value)),
+    }
+}
+
+enum HeadlessBackend {
+    Glutin {
+        context: Context<PossiblyCurrent>,
+        _event_loop: Option<event_loop::EventLoop<()>>,
+    },
+    #[cfg(target_os = "linux")]
+    Egl(EglSurfaceless),
+}
+
+pub struct HeadlessContext {
+    backend: HeadlessBackend,
+}
+
+impl HeadlessContext {
+    pub fn new(size: (u32, u32)) -> Result<Self, String> {
+        let physical_size = dpi::PhysicalSize::new(size.0, size.1);
+
+        #[cfg(target_os = "linux")]
+        {
+            use glutin::platform::unix::HeadlessContextExt;
+
+            match Self::builder().build_osmesa(physical_size) {
+                Ok(context) => {
+                    println!("Using OSMesa context");
+                    return Self::make_current(context, None);
+                }
+                Err(e) => println!("OSMesa context unavailable: {}", e),
+            }
+
+            // Needs neither an X nor a Wayland server, unlike the contexts below.
+            match EglSurfaceless::new() {
+                Ok(context) => {
+                    println!("Using EGL surfaceless context");
+                    return Ok(Self {
+                        backend: HeadlessBackend::Egl(context),
+                    });
+                }
+                Err(e) => println!("EGL surfaceless context unavailable: {}", e),
+            }
+        }
+
+        let el = std::panic::catch_unwind(event_loop::EventLoop::new)
+            .map_err(|_| "Cannot create event loop for headless context".to_string())?;
+
+        #[cfg(target_os = "linux")]
+        {
+            use glutin::platform::unix::HeadlessContextExt;
+
+            match Self::builder().build_surfaceless(&el) {
+                Ok(context) => {
+                    println!("Using surfaceless context");
+                    return Self::make_current(context, Some(el));
+                }
+                Err(e) => println!("Surfaceless context unavailable: {}", e),
+            }
+        }
+
+        let context = Self::builder()
+            .build_headless(&el, physical_size)
+            .map_err(|e| format!("Cannot create headless context: {}", e))?;
+        println!("Using pbuffer context");
+        Self::make_current(context, Some(el))
+    }
+
+    fn builder() -> ContextBuilder<'static, NotCurrent> {
+        ContextBuilder::new()
+            .with_gl(GlRequest::Latest)
+            .with_gl_profile(GlProfile::Core)
+    }
+
+    fn make_current(
+        context: Context<NotCurrent>,
+        event_loop: Option<event_loop::EventLoop<()>>,
+    ) -> Result<Self, String> {
+        let context = unsafe { context.make_current() }
+            .map_err(|(_, e)| format!("Cannot make headless context current: {}", e))?;
+        Ok(Self {
+            backend: HeadlessBackend::Glutin {
+                context,
+                _event_loop: event_loop,
+            },
+        })
+    }
+
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        match &self.backend {
+            HeadlessBackend::Glutin { context, .. } => context.get_proc_address(name),
+            #[cfg(target_os = "linux")]
+            HeadlessBackend::Egl(context) => context.get_proc_address(name),
+        }
+    }
+}
+
+// A context of Mesa's surfaceless platform, created without a config since nothing is
+// ever presented.
+#[cfg(target_os = "linux")]
+struct EglSurfaceless {
+    egl: egl::Egl,
+    display: egl::types::EGLDisplay,
+    context: egl::types::EGLContext,
+    _library: libloading::Library,
+}
+
+#[cfg(target_os = "linux")]
+impl EglSurfaceless {
+    const PLATFORM_SURFACELESS_MESA: egl::types::EGLenum = 0x31DD;
+
+    fn new() -> Result<Self, String> {
+        let library = libloading::Library::new("libEGL.so.1")
+            .map_err(|e| format!("Cannot load libEGL: {}", e))?;
+        let egl = egl::Egl::load_with(|name| {
+            let name = CString::new(name).unwrap();
+            unsafe {
+                library
+                    .get::<*const c_void>(name.as_bytes_with_nul())
+                    .map_or(std::ptr::null(), |symbol| *symbol)
+            }
+        });
+        if !egl.GetPlatformDisplay.is_loaded() {
+            return Err("eglGetPlatformDisplay is missing, EGL 1.5 is required".to_string());
+        }
+
+        unsafe {
+            let display = egl.GetPlatformDisplay(
+                Self::PLATFORM_SURFACELESS_MESA,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            );
+            if display == egl::NO_DISPLAY
+                || egl.Initialize(display, std::ptr::null_mut(), std::ptr::null_mut()) == 0
+            {
+                return Err("No surfaceless EGL display".to_string());
+            }
+            if egl.BindAPI(egl::OPENGL_API) == 0 {
+                egl.Terminate(display);
+                return Err("EGL display does not support OpenGL".to_string());
+            }
+
+            // Newest core profile first, like GlRequest::Latest.
+            let mut context = egl::NO_CONTEXT;
+            for &(major, minor) in &[(4, 6), (4, 5), (4, 4), (4, 3), (4, 2), (4, 1)] {
+                let attributes = [
+                    egl::CONTEXT_MAJOR_VERSION as i32,
+                    major,
+                    egl::CONTEXT_MINOR_VERSION as i32,
+                    minor,
+                    egl::CONTEXT_OPENGL_PROFILE_MASK as i32,
+                    egl::CONTEXT_OPENGL_CORE_PROFILE_BIT as i32,
+                    egl::NONE as i32,
+                ];
+                context = egl.CreateContext(
+                    display,
+                    std::ptr::null(),
+                    egl::NO_CONTEXT,
+                    attributes.as_ptr(),
+                );
+                if context != egl::NO_CONTEXT {
+                    break;
+                }
+            }
+            if context == egl::NO_CONTEXT {
+                egl.Terminate(display);
+                return Err(format!(
+                    "Cannot create EGL context, error 0x{:x}",
+                    egl.GetError()
+                ));
+            }
+            if egl.MakeCurrent(display, egl::NO_SURFACE, egl::NO_SURFACE, context) == 0 {
+                egl.DestroyContext(display, context);
+                egl.Terminate(display);
+                return Err("Cannot make EGL context current".to_string());
+            }
+
+            Ok(Self {
+                egl,
+                display,
+                context,
+                _library: library,
+            })
+        }
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        let name = CString::new(name).unwrap();
+        unsafe { self.egl.GetProcAddress(name.as_ptr()) as *const c_void }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for EglSurfaceless {
+    fn drop(&mut self) {
+        unsafe {
+            self.egl.MakeCurrent(
+                self.display,
+                egl::NO_SURFACE,
+                egl::NO_SURFACE,
+                egl::NO_CONTEXT,
+            );
+            self.egl.DestroyContext(self.display, self.context);
+            self.egl.Terminate(self.display);
+        }
+    }
+}
+
+// Surfaceless contexts have no default framebuffer, so every frame is drawn into
+// this one instead.
+pub struct OffscreenTarget {
+    framebuffer: Framebuffer,
+    _color: Renderbuffer,
+    _depth: Renderbuffer,
+}
+
+impl OffscreenTarget {
+    pub fn new(size: (u32, u32)) -> Result<Self, String> {
+        Self::new_with_format(size, gl::RGBA8)
+    }
+
+    pub fn new_with_format(
+        size: (u32, u32),
+        color_format: gl::types::GLenum,
+    ) -> Result<Self, String> {
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let framebuffer = Framebuffer::new();
+        let color = Renderbuffer::new(color_format, width, height);
+        let depth = Renderbuffer::new(gl::DEPTH_COMPONENT24, width, height);
+        framebuffer.attach_renderbuffer(gl::COLOR_ATTACHMENT0, &color);
+        framebuffer.attach_renderbuffer(gl::DEPTH_ATTACHMENT, &depth);
+        framebuffer.check_status()?;
+        Ok(Self {
+            framebuffer,
+            _color: color,
+            _depth: depth,
+        })
+    }
+
+    pub fn bind(&self) {
+        self.framebuffer.bind();
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.framebuffer.id()
+    }
+}
+
+pub fn run_headless(options: &HeadlessOptions) -> Result<(), String> {
+    let headless = HeadlessContext::new(options.size)?;
+    crate::load_gl(|s| headless.get_proc_address(s))?;
+
+    let target = OffscreenTarget::new(options.size)?;
+    target.bind();
+
+    let scene = options.scene.as_ref().ok_or("No scene selected")?;
+    let mut test_app = TestApp::new(options.size);
+    crate::register_scenes(&mut test_app);
+    test_app.select(scene)?;
+
+    for _ in 0..options.frames {
+        target.bind();
+        test_app.update(HeadlessOptions::FRAME_TIME);
+        test_app.render();
+        unsafe {
+            gl::Finish();
+        }
+    }
+
+    println!(
+        "Rendered {} frames of {} at {}x{}",
+        options.frames, scene, options.size.0, options.size.1
+    );
+    Ok(())
+}
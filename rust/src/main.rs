@@ -5,29 +5,41 @@ extern crate gl;
 extern crate glutin;
 #[macro_use]
 extern crate lazy_static;
-extern crate obj;
 extern crate gltf;
+extern crate mikktspace;
 
+mod animation;
 mod buffers;
 mod camera;
+mod context;
+mod frustum;
+mod gltf_loader;
+mod golden;
+mod headless;
+mod material;
+mod mesh;
+mod obj_loader;
+mod procedural;
+mod scene_graph;
+mod screenshot;
+mod shader_error;
 mod shaders;
+mod tangents;
 mod test_scenes;
 mod textures;
 mod utils;
 
-use buffers::*;
-use camera::*;
-use shaders::*;
+use context::*;
+use golden::*;
+use headless::*;
 use test_scenes::*;
-use textures::*;
-use utils::*;
 
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::time::Instant;
 
-use cgmath::*;
 use glutin::*;
+use glutin::event::VirtualKeyCode;
 
 extern "system" fn debug_callback(
     _: gl::types::GLenum,
@@ -55,7 +67,74 @@ extern "system" fn debug_callback(
     }
 }
 
+// Uniforms are set with glProgramUniform*, which is core since OpenGL 4.1.
+const REQUIRED_GL_VERSION: (i32, i32) = (4, 1);
+
+fn load_gl<F>(loader: F) -> Result<(), String>
+where
+    F: FnMut(&'static str) -> *const core::ffi::c_void,
+{
+    gl::load_with(loader);
+    let version = gl_version();
+    if version < REQUIRED_GL_VERSION {
+        return Err(format!(
+            "OpenGL {}.{} is required, the context provides {}.{}",
+            REQUIRED_GL_VERSION.0, REQUIRED_GL_VERSION.1, version.0, version.1
+        ));
+    }
+
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+    }
+
+    println!("OpenGL info");
+    unsafe {
+        println!(
+            " Vendor:   {:?}",
+            CStr::from_ptr(gl::GetString(gl::VENDOR) as *const i8)
+        );
+        println!(
+            " Renderer: {:?}",
+            CStr::from_ptr(gl::GetString(gl::RENDERER) as *const i8)
+        );
+        println!(
+            " Version:  {:?}",
+            CStr::from_ptr(gl::GetString(gl::VERSION) as *const i8)
+        );
+    }
+    Ok(())
+}
+
+fn register_scenes(test_app: &mut TestApp) {
+    test_app.register::<PbrSpheres>("PBR Spheres", VirtualKeyCode::Key1);
+    test_app.register::<PbrTexturedSpheres>("PBR Textured Spheres", VirtualKeyCode::Key2);
+    test_app.register::<PbrGlock>("PBR Glock", VirtualKeyCode::Key3);
+    test_app.register::<AnimatedModel>("Animated Model", VirtualKeyCode::Key4);
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match HeadlessOptions::from_args(&args) {
+        Ok(Some(options)) => {
+            let result = if options.golden {
+                run_golden(&options)
+            } else {
+                run_headless(&options)
+            };
+            if let Err(msg) = result {
+                println!("Headless run failed: {}", msg);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => (),
+        Err(msg) => {
+            println!("{}", msg);
+            std::process::exit(1);
+        }
+    }
+
     const WIDTH: f32 = 1600.0;
     const HEIGHT: f32 = 900.0;
 
@@ -84,47 +163,27 @@ fn main() {
 
     println!("{:?}", windowed_context.get_pixel_format());
 
-    unsafe {
-        gl::load_with(|s| windowed_context.get_proc_address(s));
-        gl::Enable(gl::DEBUG_OUTPUT);
-        gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
-    }
-
-    println!("OpenGL info");
-    unsafe {
-        println!(
-            " Vendor:   {:?}",
-            CStr::from_ptr(gl::GetString(gl::VENDOR) as *const i8)
-        );
-        println!(
-            " Renderer: {:?}",
-            CStr::from_ptr(gl::GetString(gl::RENDERER) as *const i8)
-        );
-        println!(
-            " Version:  {:?}",
-            CStr::from_ptr(gl::GetString(gl::VERSION) as *const i8)
-        );
+    if let Err(msg) = load_gl(|s| windowed_context.get_proc_address(s)) {
+        println!("{}", msg);
+        std::process::exit(1);
     }
 
     let mut window_focused = true;
     let mut test_app = TestApp::new(window.inner_size().into());
-
-    test_app.register::<PbrSpheres>("PBR Spheres", VirtualKeyCode::Key1);
-    test_app.register::<PbrTexturedSpheres>("PBR Textured Spheres", VirtualKeyCode::Key2);
-    test_app.register::<PbrGlock>("PBR Glock", VirtualKeyCode::Key3);
+    register_scenes(&mut test_app);
 
     let mut time = Instant::now();
     let mut delta_t = time.elapsed();
 
     use event::*;
-    el.run(move |event, _, mut control_flow| {
+    el.run(move |event, _, control_flow| {
         *control_flow = event_loop::ControlFlow::Poll;
         match event {
             Event::LoopDestroyed => return,
             Event::WindowEvent { ref event, .. } => match event {
                 WindowEvent::CloseRequested => *control_flow = event_loop::ControlFlow::Exit,
                 WindowEvent::Resized(size) => {
-                    windowed_context.resize(size.clone());
+                    windowed_context.resize(*size);
 
                     test_app.set_framebuffer_size((size.width, size.height));
                 }
@@ -149,12 +208,20 @@ fn main() {
 
                 delta_t = time.elapsed();
                 time = Instant::now();
-                println!("Time: {}ms", delta_t.as_micros() as f32 / 1000.0);
+                match test_app.cull_stats() {
+                    Some(stats) => println!(
+                        "Time: {}ms, culled: {}/{}",
+                        delta_t.as_micros() as f32 / 1000.0,
+                        stats.culled,
+                        stats.total()
+                    ),
+                    None => println!("Time: {}ms", delta_t.as_micros() as f32 / 1000.0),
+                }
             }
             _ => (),
         }
 
-        test_app.handle_event(&event, &mut control_flow);
+        test_app.handle_event(&event, control_flow);
         if window_focused {
             windowed_context.window().request_redraw();
         }
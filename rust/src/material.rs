@@ -0,0 +1,273 @@
+extern crate cgmath;
+extern crate gl;
+extern crate image;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use cgmath::*;
+use image::*;
+
+use crate::shaders::*;
+use crate::textures::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureTransform {
+    pub offset: Vector2<f32>,
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+}
+
+impl TextureTransform {
+    pub fn identity() -> Self {
+        Self {
+            offset: vec2(0.0, 0.0),
+            rotation: 0.0,
+            scale: vec2(1.0, 1.0),
+        }
+    }
+
+    // Same order as KHR_texture_transform: scale, then rotate, then translate.
+    pub fn to_matrix(self) -> Matrix3<f32> {
+        let (sin, cos) = self.rotation.sin_cos();
+        let translation = Matrix3::from_cols(
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            self.offset.extend(1.0),
+        );
+        let rotation = Matrix3::from_cols(
+            vec3(cos, -sin, 0.0),
+            vec3(sin, cos, 0.0),
+            vec3(0.0, 0.0, 1.0),
+        );
+        let scale = Matrix3::from_diagonal(self.scale.extend(1.0));
+        translation * rotation * scale
+    }
+}
+
+#[derive(Clone)]
+pub struct MaterialTexture {
+    pub texture: Rc<Texture2D>,
+    pub transform: TextureTransform,
+    // Index of the TEXCOORD_n set the texture is sampled with, 0 or 1.
+    pub tex_coord: u32,
+}
+
+impl MaterialTexture {
+    pub fn new(texture: Rc<Texture2D>) -> Self {
+        Self {
+            texture,
+            transform: TextureTransform::identity(),
+            tex_coord: 0,
+        }
+    }
+}
+
+// Textures shared between materials, keyed by path and sRGB decoding.
+pub type TextureFileCache = HashMap<(String, bool), Rc<Texture2D>>;
+
+fn open_image(path: &str) -> Option<DynamicImage> {
+    match open(path) {
+        Ok(image) => Some(image.flipv()),
+        Err(e) => {
+            println!("warning: ignoring texture {}: {}", path, e);
+            None
+        }
+    }
+}
+
+// Missing textures are common in downloaded models, so they only produce a warning and
+// the material falls back to its factors.
+pub fn load_texture_file(
+    path: &str,
+    srgb: bool,
+    cache: &mut TextureFileCache,
+) -> Option<Rc<Texture2D>> {
+    let key = (path.to_string(), srgb);
+    if let Some(loaded) = cache.get(&key) {
+        return Some(loaded.clone());
+    }
+    let image = open_image(path)?.to_rgba();
+    let internal_format = if srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 };
+    let loaded = Rc::new(Texture2D::new_from_pixels(
+        internal_format,
+        image.width(),
+        image.height(),
+        gl::RGBA,
+        &image.into_raw(),
+    ));
+    cache.insert(key, loaded.clone());
+    Some(loaded)
+}
+
+// Packs separate roughness and metalness images into the glTF layout, roughness in the
+// G channel and metalness in the B channel. A missing image leaves its channel at 1 so
+// only the factor applies.
+pub fn load_metallic_roughness_files(
+    roughness_path: Option<&str>,
+    metallic_path: Option<&str>,
+) -> Option<Rc<Texture2D>> {
+    let roughness = roughness_path.and_then(|path| open_image(path).map(|i| i.to_luma()));
+    let metallic = metallic_path.and_then(|path| open_image(path).map(|i| i.to_luma()));
+    let (width, height) = match (&roughness, &metallic) {
+        (Some(roughness), _) => roughness.dimensions(),
+        (None, Some(metallic)) => metallic.dimensions(),
+        (None, None) => return None,
+    };
+    let resize = |image: GrayImage| {
+        if image.dimensions() == (width, height) {
+            image
+        } else {
+            imageops::resize(&image, width, height, FilterType::Triangle)
+        }
+    };
+    let roughness = roughness.map(|image| resize(image).into_raw());
+    let metallic = metallic.map(|image| resize(image).into_raw());
+
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for i in 0..(width * height) as usize {
+        pixels.push(0);
+        pixels.push(roughness.as_ref().map_or(255, |pixels| pixels[i]));
+        pixels.push(metallic.as_ref().map_or(255, |pixels| pixels[i]));
+    }
+    Some(Rc::new(Texture2D::new_from_pixels(
+        gl::RGB8,
+        width,
+        height,
+        gl::RGB,
+        &pixels,
+    )))
+}
+
+// glTF metallic-roughness material, rendered by shaders/gltf_pbr_ibl.frag. A slot
+// without a texture is skipped by the shader, leaving just its factor, so untextured
+// materials need no placeholder textures.
+#[derive(Clone)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: Vector4<f32>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: Vector3<f32>,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    // sRGB, alpha in the A channel.
+    pub base_color_texture: Option<MaterialTexture>,
+    // Linear, roughness in the G channel and metalness in the B channel.
+    pub metallic_roughness_texture: Option<MaterialTexture>,
+    pub normal_texture: Option<MaterialTexture>,
+    // Linear, occlusion in the R channel.
+    pub occlusion_texture: Option<MaterialTexture>,
+    // sRGB.
+    pub emissive_texture: Option<MaterialTexture>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: vec4(1.0, 1.0, 1.0, 1.0),
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            emissive_factor: vec3(0.0, 0.0, 0.0),
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl Material {
+    // Slots 0 to 2 hold the image based lighting maps.
+    pub const FIRST_TEXTURE_SLOT: u32 = 3;
+    const TEXTURE_NAMES: [&'static str; 5] = [
+        "base_color",
+        "metallic_roughness",
+        "normal",
+        "occlusion",
+        "emissive",
+    ];
+
+    // Loads a material from a directory of albedo.png, normal.png, metallic.png,
+    // roughness.png and ao.png. Missing images leave their slot to the factor.
+    pub fn load(directory: &str, cache: &mut TextureFileCache) -> Result<Self, String> {
+        if !Path::new(directory).is_dir() {
+            return Err(format!("Material directory not found: {}", directory));
+        }
+        let file = |name: &str| format!("{}/{}.png", directory, name);
+        let existing = |name: &str| Some(file(name)).filter(|path| Path::new(path).is_file());
+        let mut load = |name: &str, srgb| {
+            existing(name)
+                .and_then(|path| load_texture_file(&path, srgb, cache))
+                .map(MaterialTexture::new)
+        };
+        Ok(Self {
+            name: Path::new(directory)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            base_color_texture: load("albedo", true),
+            normal_texture: load("normal", false),
+            occlusion_texture: load("ao", false),
+            metallic_roughness_texture: load_metallic_roughness_files(
+                existing("roughness").as_deref(),
+                existing("metallic").as_deref(),
+            )
+            .map(MaterialTexture::new),
+            ..Self::default()
+        })
+    }
+
+    // Assigns the sampler uniforms, needs to be called once after every (re)link.
+    pub fn setup_shader(shader: &Shader) {
+        for (i, name) in Self::TEXTURE_NAMES.iter().enumerate() {
+            shader.set_uniform_1i(
+                &format!("{}_map", name),
+                &((Self::FIRST_TEXTURE_SLOT as usize + i) as i32),
+            );
+        }
+    }
+
+    fn textures(&self) -> [&Option<MaterialTexture>; 5] {
+        [
+            &self.base_color_texture,
+            &self.metallic_roughness_texture,
+            &self.normal_texture,
+            &self.occlusion_texture,
+            &self.emissive_texture,
+        ]
+    }
+
+    // Sets the factors, textures and texture transforms in one go.
+    pub fn bind(&self, shader: &Shader) {
+        shader.set_uniform_4f("base_color_factor", &self.base_color_factor);
+        shader.set_uniform_1f("metallic_factor", &self.metallic_factor);
+        shader.set_uniform_1f("roughness_factor", &self.roughness_factor);
+        shader.set_uniform_3f("emissive_factor", &self.emissive_factor);
+        shader.set_uniform_1f("normal_scale", &self.normal_scale);
+        shader.set_uniform_1f("occlusion_strength", &self.occlusion_strength);
+
+        let identity = TextureTransform::identity();
+        for (i, (name, texture)) in Self::TEXTURE_NAMES
+            .iter()
+            .zip(self.textures().iter())
+            .enumerate()
+        {
+            shader.set_uniform_1i(&format!("has_{}_map", name), &(texture.is_some() as i32));
+            if let Some(texture) = texture {
+                texture
+                    .texture
+                    .set_slot(&(Self::FIRST_TEXTURE_SLOT + i as u32));
+            }
+            let (transform, tex_coord) = texture.as_ref().map_or((&identity, 0), |texture| {
+                (&texture.transform, texture.tex_coord)
+            });
+            shader.set_uniform_mat3f(&format!("{}_uv_transform", name), &transform.to_matrix());
+            shader.set_uniform_1i(&format!("{}_uv_set", name), &(tex_coord as i32));
+        }
+    }
+}
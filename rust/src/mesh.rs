@@ -0,0 +1,377 @@
+extern crate cgmath;
+extern crate gl;
+
+use cgmath::*;
+
+use crate::buffers::*;
+use crate::tangents::*;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4],
+}
+
+impl Vertex for MeshVertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::new()
+            .float(0, 3)
+            .float(1, 2)
+            .float(2, 3)
+            .float(3, 4)
+    }
+}
+
+// Fills in the tangents of `vertices`, see generate_tangents.
+pub fn compute_mesh_tangents(vertices: &mut [MeshVertex], triangles: &[[u32; 3]]) {
+    let positions = vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+    let normals = vertices.iter().map(|v| v.normal).collect::<Vec<_>>();
+    let uvs = vertices.iter().map(|v| v.uv).collect::<Vec<_>>();
+    let tangents = generate_tangents(&positions, &normals, &uvs, triangles);
+    for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+        vertex.tangent = tangent;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    // An empty set of points gives a degenerate box at the origin.
+    pub fn from_points<I: IntoIterator<Item = Vector3<f32>>>(points: I) -> Self {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(point) => point,
+            None => {
+                return Self {
+                    min: Vector3::zero(),
+                    max: Vector3::zero(),
+                }
+            }
+        };
+        points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, point| Self {
+                min: vec3(
+                    aabb.min.x.min(point.x),
+                    aabb.min.y.min(point.y),
+                    aabb.min.z.min(point.z),
+                ),
+                max: vec3(
+                    aabb.max.x.max(point.x),
+                    aabb.max.y.max(point.y),
+                    aabb.max.z.max(point.z),
+                ),
+            },
+        )
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            vec3(a.x, a.y, a.z),
+            vec3(b.x, a.y, a.z),
+            vec3(a.x, b.y, a.z),
+            vec3(b.x, b.y, a.z),
+            vec3(a.x, a.y, b.z),
+            vec3(b.x, a.y, b.z),
+            vec3(a.x, b.y, b.z),
+            vec3(b.x, b.y, b.z),
+        ]
+    }
+
+    // The box around the transformed corners, which is looser than the original box.
+    pub fn transform(&self, transform: &Matrix4<f32>) -> Self {
+        Self::from_points(self.corners().iter().map(|corner| {
+            transform
+                .transform_point(Point3::from_vec(*corner))
+                .to_vec()
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centered on the bounding box of the points, which is not the tightest sphere but
+    // is cheap and stable.
+    pub fn from_points<I: IntoIterator<Item = Vector3<f32>> + Clone>(points: I) -> Self {
+        let center = Aabb::from_points(points.clone()).center();
+        let radius = points
+            .into_iter()
+            .map(|point| (point - center).magnitude())
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+
+    // Scales the radius by the largest axis scale of `transform`.
+    pub fn transform(&self, transform: &Matrix4<f32>) -> Self {
+        let scale = transform
+            .x
+            .truncate()
+            .magnitude()
+            .max(transform.y.truncate().magnitude())
+            .max(transform.z.truncate().magnitude());
+        Self {
+            center: transform
+                .transform_point(Point3::from_vec(self.center))
+                .to_vec(),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+// CPU side geometry of a mesh, in the same topology as the uploaded buffers.
+#[derive(Clone, Debug)]
+pub struct MeshData {
+    pub topology: gl::types::GLenum,
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Option<Vec<u32>>,
+}
+
+impl MeshData {
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|v| Vector3::from(v.position)))
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_points(self.vertices.iter().map(|v| Vector3::from(v.position)))
+    }
+}
+
+pub struct Mesh {
+    vertex_array: VertexArray,
+    _vertex_buffer: VertexBuffer,
+    index_buffer: Option<IndexBuffer>,
+    topology: gl::types::GLenum,
+    vertex_count: usize,
+    bounds: Aabb,
+    bounding_sphere: BoundingSphere,
+    data: Option<MeshData>,
+}
+
+impl Mesh {
+    pub fn new(data: &MeshData) -> Self {
+        let vertex_array = VertexArray::new();
+        vertex_array.bind();
+        let vertex_buffer = VertexBuffer::new_static(&data.vertices);
+        vertex_buffer.bind();
+        vertex_array.set_vertex_type::<MeshVertex>();
+        let index_buffer = data.indices.as_ref().map(|indices| {
+            let index_buffer = IndexBuffer::new_static_narrowest(indices);
+            index_buffer.bind();
+            index_buffer
+        });
+        Self {
+            vertex_array,
+            _vertex_buffer: vertex_buffer,
+            index_buffer,
+            topology: data.topology,
+            vertex_count: data.vertices.len(),
+            bounds: data.bounds(),
+            bounding_sphere: data.bounding_sphere(),
+            data: None,
+        }
+    }
+
+    // Keeps `data` around for picking and export.
+    pub fn new_with_cpu_copy(data: MeshData) -> Self {
+        let mut mesh = Self::new(&data);
+        mesh.data = Some(data);
+        mesh
+    }
+
+    pub fn vertex_array(&self) -> &VertexArray {
+        &self.vertex_array
+    }
+
+    pub fn topology(&self) -> gl::types::GLenum {
+        self.topology
+    }
+
+    pub fn index_type(&self) -> Option<gl::types::GLenum> {
+        self.index_buffer.as_ref().map(|ib| ib.index_type())
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounding_sphere
+    }
+
+    pub fn data(&self) -> Option<&MeshData> {
+        self.data.as_ref()
+    }
+
+    pub fn draw(&self) {
+        self.draw_instanced(1);
+    }
+
+    pub fn draw_instanced(&self, instances: usize) {
+        self.draw_instanced_from(0, instances);
+    }
+
+    // Per-instance attributes start at `first_instance`, a nonzero one needs OpenGL 4.2
+    // or GL_ARB_base_instance.
+    pub fn draw_instanced_from(&self, first_instance: usize, instances: usize) {
+        self.vertex_array.bind();
+        match &self.index_buffer {
+            Some(index_buffer) => {
+                index_buffer.bind();
+                unsafe {
+                    if first_instance == 0 {
+                        gl::DrawElementsInstanced(
+                            self.topology,
+                            index_buffer.count() as i32,
+                            index_buffer.index_type(),
+                            std::ptr::null(),
+                            instances as i32,
+                        );
+                    } else {
+                        gl::DrawElementsInstancedBaseInstance(
+                            self.topology,
+                            index_buffer.count() as i32,
+                            index_buffer.index_type(),
+                            std::ptr::null(),
+                            instances as i32,
+                            first_instance as u32,
+                        );
+                    }
+                }
+            }
+            None => unsafe {
+                if first_instance == 0 {
+                    gl::DrawArraysInstanced(
+                        self.topology,
+                        0,
+                        self.vertex_count as i32,
+                        instances as i32,
+                    );
+                } else {
+                    gl::DrawArraysInstancedBaseInstance(
+                        self.topology,
+                        0,
+                        self.vertex_count as i32,
+                        instances as i32,
+                        first_instance as u32,
+                    );
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn aabb_from_points() {
+        let aabb = Aabb::from_points(vec![
+            vec3(1.0, -2.0, 0.5),
+            vec3(-1.0, 3.0, 0.0),
+            vec3(0.0, 0.0, -4.0),
+        ]);
+        assert_eq!(aabb.min, vec3(-1.0, -2.0, -4.0));
+        assert_eq!(aabb.max, vec3(1.0, 3.0, 0.5));
+
+        let empty = Aabb::from_points(Vec::new());
+        assert_eq!(empty.min, Vector3::zero());
+        assert_eq!(empty.max, Vector3::zero());
+    }
+
+    #[test]
+    fn aabb_transform() {
+        let aabb = Aabb {
+            min: vec3(0.0, 0.0, 0.0),
+            max: vec3(1.0, 2.0, 3.0),
+        };
+
+        let moved = aabb.transform(&Matrix4::from_translation(vec3(1.0, -1.0, 2.0)));
+        assert_close(moved.min, vec3(1.0, -1.0, 2.0));
+        assert_close(moved.max, vec3(2.0, 1.0, 5.0));
+
+        // A quarter turn around y maps (x, y, z) to (z, y, -x).
+        let rotated = aabb.transform(&Matrix4::from_angle_y(Deg(90.0)));
+        assert_close(rotated.min, vec3(0.0, 0.0, -1.0));
+        assert_close(rotated.max, vec3(3.0, 2.0, 0.0));
+
+        // An eighth of a turn grows the box around the rotated corners.
+        let unit = Aabb {
+            min: vec3(-1.0, -1.0, -1.0),
+            max: vec3(1.0, 1.0, 1.0),
+        };
+        let rotated = unit.transform(&Matrix4::from_angle_z(Deg(45.0)));
+        let half_diagonal = 2.0f32.sqrt();
+        assert_close(rotated.min, vec3(-half_diagonal, -half_diagonal, -1.0));
+        assert_close(rotated.max, vec3(half_diagonal, half_diagonal, 1.0));
+    }
+
+    #[test]
+    fn bounding_sphere_from_points() {
+        let sphere = BoundingSphere::from_points(vec![
+            vec3(-1.0, 0.0, 0.0),
+            vec3(3.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+        ]);
+        assert_close(sphere.center, vec3(1.0, 0.5, 0.0));
+        assert!((sphere.radius - 4.25f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounding_sphere_transform() {
+        let sphere = BoundingSphere {
+            center: vec3(1.0, 0.0, 0.0),
+            radius: 2.0,
+        };
+
+        let moved = sphere.transform(&Matrix4::from_translation(vec3(0.0, 5.0, 0.0)));
+        assert_close(moved.center, vec3(1.0, 5.0, 0.0));
+        assert!((moved.radius - 2.0).abs() < 1e-5);
+
+        // The radius follows the largest scale so the sphere still encloses the mesh.
+        let scaled = sphere.transform(
+            &(Matrix4::from_translation(vec3(0.0, 0.0, -1.0))
+                * Matrix4::from_angle_y(Deg(90.0))
+                * Matrix4::from_nonuniform_scale(2.0, 3.0, 1.0)),
+        );
+        assert_close(scaled.center, vec3(0.0, 0.0, -3.0));
+        assert!((scaled.radius - 6.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,548 @@
+extern crate cgmath;
+extern crate gl;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use cgmath::*;
+
+use crate::frustum::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::shaders::*;
+use crate::tangents::*;
+
+// A material of an MTL file. Texture paths are already resolved relative to the file.
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+    pub shininess: f32,
+    // PBR extension, Pr and Pm.
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub diffuse_map: Option<String>,
+    pub bump_map: Option<String>,
+    pub roughness_map: Option<String>,
+    pub metallic_map: Option<String>,
+}
+
+impl MtlMaterial {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            diffuse: vec3(1.0, 1.0, 1.0),
+            specular: vec3(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            roughness: None,
+            metallic: None,
+            diffuse_map: None,
+            bump_map: None,
+            roughness_map: None,
+            metallic_map: None,
+        }
+    }
+
+    // Without Pr the roughness is derived from the Blinn-Phong exponent.
+    fn to_material(&self, cache: &mut TextureFileCache) -> Material {
+        let roughness_factor = match (self.roughness, &self.roughness_map) {
+            (Some(roughness), _) => roughness,
+            (None, Some(_)) => 1.0,
+            (None, None) => (2.0 / (self.shininess + 2.0)).sqrt(),
+        };
+        let metallic_factor = match (self.metallic, &self.metallic_map) {
+            (Some(metallic), _) => metallic,
+            (None, Some(_)) => 1.0,
+            (None, None) => 0.0,
+        };
+        Material {
+            name: Some(self.name.clone()),
+            base_color_factor: self.diffuse.extend(1.0),
+            metallic_factor,
+            roughness_factor,
+            base_color_texture: self
+                .diffuse_map
+                .as_ref()
+                .and_then(|path| load_texture_file(path, true, cache))
+                .map(MaterialTexture::new),
+            metallic_roughness_texture: load_metallic_roughness_files(
+                self.roughness_map.as_deref(),
+                self.metallic_map.as_deref(),
+            )
+            .map(MaterialTexture::new),
+            // Exporters like Blender write tangent space normal maps as bump maps.
+            normal_texture: self
+                .bump_map
+                .as_ref()
+                .and_then(|path| load_texture_file(path, false, cache))
+                .map(MaterialTexture::new),
+            ..Material::default()
+        }
+    }
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(if min == max {
+            format!("expected {} values, found {}", min, args.len())
+        } else {
+            format!("expected {} to {} values, found {}", min, max, args.len())
+        });
+    }
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f32>()
+                .map_err(|_| format!("invalid number {}", arg))
+        })
+        .collect()
+}
+
+fn parse_float(args: &[&str]) -> Result<f32, String> {
+    Ok(parse_floats(args, 1, 1)?[0])
+}
+
+fn parse_color(args: &[&str]) -> Result<Vector3<f32>, String> {
+    let values = parse_floats(args, 1, 3)?;
+    match values.len() {
+        1 => Ok(vec3(values[0], values[0], values[0])),
+        3 => Ok(vec3(values[0], values[1], values[2])),
+        _ => Err("expected 1 or 3 color components".to_string()),
+    }
+}
+
+// Texture statements may carry options like "-bm 1.0" before the file name, which is
+// always the last argument.
+fn parse_texture_path(args: &[&str], dir: &Path) -> Result<String, String> {
+    let file = args.last().ok_or("missing texture file name")?;
+    Ok(dir.join(file).to_string_lossy().into_owned())
+}
+
+fn statements(source: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(index, line)| {
+        let line = line.split('#').next().unwrap();
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next()?;
+        Some((index + 1, keyword, tokens.collect()))
+    })
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))
+}
+
+pub fn parse_mtl(source: &str, path: &str) -> Result<Vec<MtlMaterial>, String> {
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for (line, keyword, args) in statements(source) {
+        let located = |message: String| format!("{}:{}: {}", path, line, message);
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(located("newmtl without a material name".to_string()));
+            }
+            materials.push(MtlMaterial::new(args.join(" ")));
+            continue;
+        }
+        let material = materials
+            .last_mut()
+            .ok_or_else(|| located(format!("{} before the first newmtl", keyword)))?;
+        let result = match keyword {
+            "Kd" => parse_color(&args).map(|color| material.diffuse = color),
+            "Ks" => parse_color(&args).map(|color| material.specular = color),
+            "Ns" => parse_float(&args).map(|value| material.shininess = value),
+            "Pr" => parse_float(&args).map(|value| material.roughness = Some(value)),
+            "Pm" => parse_float(&args).map(|value| material.metallic = Some(value)),
+            "map_Kd" => parse_texture_path(&args, dir).map(|p| material.diffuse_map = Some(p)),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                parse_texture_path(&args, dir).map(|p| material.bump_map = Some(p))
+            }
+            "map_Pr" => parse_texture_path(&args, dir).map(|p| material.roughness_map = Some(p)),
+            "map_Pm" => parse_texture_path(&args, dir).map(|p| material.metallic_map = Some(p)),
+            // Ka, Ni, d, illum and the remaining Phong statements have no use here.
+            _ => Ok(()),
+        };
+        result.map_err(|msg| located(format!("{}: {}", keyword, msg)))?;
+    }
+    Ok(materials)
+}
+
+// OBJ indices start at 1, negative ones count back from the last element defined.
+fn resolve_index(token: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = token
+        .parse::<i64>()
+        .map_err(|_| format!("invalid {} index {}", kind, token))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range, {} defined so far",
+            kind, token, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+// Faces of one group using one material.
+struct ObjGroup {
+    name: Option<String>,
+    material: Option<usize>,
+    vertices: Vec<MeshVertex>,
+    indices: Vec<u32>,
+    // Vertices with a normal are shared between faces, keyed by their v/vt/vn indices.
+    shared_vertices: HashMap<(usize, Option<usize>, usize), u32>,
+}
+
+impl ObjGroup {
+    fn new(name: Option<String>, material: Option<usize>) -> Self {
+        Self {
+            name,
+            material,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            shared_vertices: HashMap::new(),
+        }
+    }
+}
+
+struct ObjData {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    groups: Vec<ObjGroup>,
+    materials: Vec<MtlMaterial>,
+}
+
+impl ObjData {
+    fn current_group(&mut self) -> &mut ObjGroup {
+        self.groups.last_mut().unwrap()
+    }
+
+    // Starts a new submesh unless the current one is still empty.
+    fn start_group(&mut self, name: Option<String>, material: Option<usize>) {
+        let current = self.current_group();
+        if current.indices.is_empty() {
+            current.name = name;
+            current.material = material;
+        } else {
+            self.groups.push(ObjGroup::new(name, material));
+        }
+    }
+
+    fn add_face(&mut self, args: &[&str]) -> Result<(), String> {
+        if args.len() < 3 {
+            return Err(format!(
+                "face needs at least 3 vertices, found {}",
+                args.len()
+            ));
+        }
+        let mut corners = Vec::with_capacity(args.len());
+        for arg in args {
+            let mut parts = arg.split('/');
+            let position = resolve_index(parts.next().unwrap(), self.positions.len(), "vertex")?;
+            let uv = match parts.next() {
+                Some(token) if !token.is_empty() => {
+                    Some(resolve_index(token, self.uvs.len(), "texture coordinate")?)
+                }
+                _ => None,
+            };
+            let normal = match parts.next() {
+                Some(token) => Some(resolve_index(token, self.normals.len(), "normal")?),
+                None => None,
+            };
+            if parts.next().is_some() {
+                return Err(format!("invalid face vertex {}", arg));
+            }
+            corners.push((position, uv, normal));
+        }
+
+        let face_normal = {
+            let p = |i: usize| Vector3::from(self.positions[corners[i].0]);
+            (p(1) - p(0)).cross(p(2) - p(0)).normalize()
+        };
+
+        let mut indices = Vec::with_capacity(corners.len());
+        for (position, uv, normal) in corners {
+            let vertex = MeshVertex {
+                position: self.positions[position],
+                uv: uv.map_or([0.0, 0.0], |uv| self.uvs[uv]),
+                normal: normal.map_or(face_normal.into(), |normal| self.normals[normal]),
+                tangent: [0.0; 4],
+            };
+            let ObjGroup {
+                vertices,
+                shared_vertices,
+                ..
+            } = self.current_group();
+            let index = match normal {
+                Some(normal) => *shared_vertices
+                    .entry((position, uv, normal))
+                    .or_insert_with(|| {
+                        vertices.push(vertex);
+                        vertices.len() as u32 - 1
+                    }),
+                None => {
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                }
+            };
+            indices.push(index);
+        }
+
+        // Polygons are triangulated as a fan around their first vertex.
+        let group = self.current_group();
+        for i in 1..indices.len() - 1 {
+            group
+                .indices
+                .extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+        }
+        Ok(())
+    }
+}
+
+fn parse_obj(source: &str, path: &str) -> Result<ObjData, String> {
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let mut data = ObjData {
+        positions: Vec::new(),
+        uvs: Vec::new(),
+        normals: Vec::new(),
+        groups: vec![ObjGroup::new(None, None)],
+        materials: Vec::new(),
+    };
+    for (line, keyword, args) in statements(source) {
+        let located = |message: String| format!("{}:{}: {}", path, line, message);
+        let result = match keyword {
+            "v" => parse_floats(&args, 3, 4).map(|v| data.positions.push([v[0], v[1], v[2]])),
+            "vt" => parse_floats(&args, 1, 3)
+                .map(|v| data.uvs.push([v[0], v.get(1).cloned().unwrap_or(0.0)])),
+            "vn" => parse_floats(&args, 3, 3).map(|v| data.normals.push([v[0], v[1], v[2]])),
+            "f" => data.add_face(&args),
+            "g" | "o" => {
+                let name = if args.is_empty() {
+                    None
+                } else {
+                    Some(args.join(" "))
+                };
+                let material = data.current_group().material;
+                data.start_group(name, material);
+                Ok(())
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                match data.materials.iter().position(|m| m.name == name) {
+                    Some(material) => {
+                        let name = data.current_group().name.clone();
+                        data.start_group(name, Some(material));
+                        Ok(())
+                    }
+                    None => Err(format!("unknown material {}", name)),
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    Err("missing file name".to_string())
+                } else {
+                    for file in &args {
+                        let mtl_path = dir.join(file).to_string_lossy().into_owned();
+                        let materials = parse_mtl(&read_file(&mtl_path)?, &mtl_path)?;
+                        data.materials.extend(materials);
+                    }
+                    Ok(())
+                }
+            }
+            // Smoothing groups, lines and points are ignored.
+            _ => Ok(()),
+        };
+        result.map_err(|msg| located(format!("{}: {}", keyword, msg)))?;
+    }
+    data.groups.retain(|group| !group.indices.is_empty());
+    for group in &mut data.groups {
+        compute_mesh_tangents(&mut group.vertices, &triangle_list(&group.indices));
+    }
+    Ok(data)
+}
+
+pub struct ObjSubmesh {
+    pub name: Option<String>,
+    pub material: Option<usize>,
+    pub mesh: Mesh,
+}
+
+impl ObjSubmesh {
+    fn new(group: ObjGroup) -> Self {
+        Self {
+            name: group.name,
+            material: group.material,
+            mesh: Mesh::new(&MeshData {
+                topology: gl::TRIANGLES,
+                vertices: group.vertices,
+                indices: Some(group.indices),
+            }),
+        }
+    }
+}
+
+// An OBJ file split into one submesh per group and material, with the materials of
+// its MTL libraries converted for shaders/gltf_pbr_ibl.frag.
+pub struct ObjModel {
+    pub submeshes: Vec<ObjSubmesh>,
+    pub materials: Vec<Material>,
+    // Used by faces before the first usemtl.
+    pub default_material: Material,
+}
+
+impl ObjModel {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = parse_obj(&read_file(path)?, path)?;
+        let mut texture_cache = TextureFileCache::new();
+        Ok(Self {
+            submeshes: data.groups.into_iter().map(ObjSubmesh::new).collect(),
+            materials: data
+                .materials
+                .iter()
+                .map(|material| material.to_material(&mut texture_cache))
+                .collect(),
+            default_material: Material::default(),
+        })
+    }
+
+    pub fn draw(&self, shader: &Shader, transform: &Matrix4<f32>, frustum: &Frustum) -> CullStats {
+        shader.set_uniform_mat4f("model", transform);
+        let mut stats = CullStats::default();
+        for submesh in &self.submeshes {
+            let mesh = &submesh.mesh;
+            if !stats.record(frustum.is_visible(mesh.bounds(), mesh.bounding_sphere(), transform)) {
+                continue;
+            }
+            let material = submesh
+                .material
+                .map_or(&self.default_material, |index| &self.materials[index]);
+            material.bind(shader);
+            submesh.mesh.draw();
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n";
+
+    #[test]
+    fn parse_mtl_materials() {
+        let source = "# comment\n\
+                      newmtl steel\n\
+                      Kd 0.5 0.25 0.125\n\
+                      Ns 98\n\
+                      Pr 0.3\n\
+                      Pm 1\n\
+                      map_Kd textures/albedo.png\n\
+                      map_Bump -bm 1.0 textures/normal.png\n\
+                      illum 2\n\
+                      newmtl rubber\n\
+                      Kd 0.1\n";
+        let materials = parse_mtl(source, "models/gun.mtl").unwrap();
+        assert_eq!(materials.len(), 2);
+        let steel = &materials[0];
+        assert_eq!(steel.name, "steel");
+        assert_eq!(steel.diffuse, vec3(0.5, 0.25, 0.125));
+        assert_eq!(steel.shininess, 98.0);
+        assert_eq!(steel.roughness, Some(0.3));
+        assert_eq!(steel.metallic, Some(1.0));
+        let texture = |file: &str| {
+            Path::new("models")
+                .join(file)
+                .to_string_lossy()
+                .into_owned()
+        };
+        assert_eq!(steel.diffuse_map, Some(texture("textures/albedo.png")));
+        assert_eq!(steel.bump_map, Some(texture("textures/normal.png")));
+        assert_eq!(materials[1].diffuse, vec3(0.1, 0.1, 0.1));
+        assert_eq!(materials[1].roughness, None);
+    }
+
+    #[test]
+    fn parse_mtl_errors() {
+        let msg = parse_mtl("Kd 1 1 1\nnewmtl a\n", "a.mtl").err().unwrap();
+        assert_eq!(msg, "a.mtl:1: Kd before the first newmtl");
+        let msg = parse_mtl("newmtl\n", "a.mtl").err().unwrap();
+        assert_eq!(msg, "a.mtl:1: newmtl without a material name");
+        let msg = parse_mtl("newmtl a\nKd 1 x 1\n", "a.mtl").err().unwrap();
+        assert_eq!(msg, "a.mtl:2: Kd: invalid number x");
+        let msg = parse_mtl("newmtl a\nNs 1 2\n", "a.mtl").err().unwrap();
+        assert_eq!(msg, "a.mtl:2: Ns: expected 1 values, found 2");
+        let msg = parse_mtl("newmtl a\nmap_Kd\n", "a.mtl").err().unwrap();
+        assert_eq!(msg, "a.mtl:2: map_Kd: missing texture file name");
+    }
+
+    #[test]
+    fn resolve_indices() {
+        assert_eq!(resolve_index("1", 3, "vertex"), Ok(0));
+        assert_eq!(resolve_index("3", 3, "vertex"), Ok(2));
+        assert_eq!(resolve_index("-1", 3, "vertex"), Ok(2));
+        assert_eq!(resolve_index("-3", 3, "vertex"), Ok(0));
+        assert_eq!(
+            resolve_index("0", 3, "vertex"),
+            Err("vertex index 0 is out of range, 3 defined so far".to_string())
+        );
+        assert!(resolve_index("4", 3, "vertex").is_err());
+        assert!(resolve_index("-4", 3, "vertex").is_err());
+        assert!(resolve_index("1", 0, "normal").is_err());
+        assert_eq!(
+            resolve_index("x", 3, "texture coordinate"),
+            Err("invalid texture coordinate index x".to_string())
+        );
+    }
+
+    #[test]
+    fn faces_are_triangulated() {
+        let source = format!("{}v 1 1 0\nf 1/1/1 2/2/1 4/3/1 3/3/1\nf 1 2 3\n", TRIANGLE);
+        let data = parse_obj(&source, "quad.obj").unwrap();
+        assert_eq!(data.groups.len(), 1);
+        let group = &data.groups[0];
+        // The quad shares its normal vertices, the triangle without normals does not.
+        assert_eq!(group.vertices.len(), 7);
+        assert_eq!(group.indices, vec![0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        assert_eq!(group.vertices[4].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(group.vertices[1].uv, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn face_errors() {
+        let cases = [
+            ("f 1 2\n", "face needs at least 3 vertices, found 2"),
+            (
+                "f 1 2 4\n",
+                "vertex index 4 is out of range, 3 defined so far",
+            ),
+            ("f 1/4 2 3\n", "texture coordinate index 4 is out of range"),
+            ("f 1//2 2 3\n", "normal index 2 is out of range"),
+            ("f 1/1/1/1 2 3\n", "invalid face vertex 1/1/1/1"),
+            ("usemtl missing\n", "usemtl: unknown material missing"),
+        ];
+        for (statement, expected) in &cases {
+            let source = format!("{}{}", TRIANGLE, statement);
+            let msg = parse_obj(&source, "bad.obj").err().unwrap();
+            assert!(msg.starts_with("bad.obj:8: "), "{}", msg);
+            assert!(
+                msg.contains(expected),
+                "{} does not contain {}",
+                msg,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn groups_split_submeshes() {
+        let source = format!("{}g first\nf 1 2 3\ng second\nf 3 2 1\ng empty\n", TRIANGLE);
+        let data = parse_obj(&source, "groups.obj").unwrap();
+        let names: Vec<_> = data.groups.iter().map(|g| g.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("first"), Some("second")]);
+    }
+}
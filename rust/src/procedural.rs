@@ -0,0 +1,520 @@
+extern crate cgmath;
+extern crate gl;
+
+use cgmath::*;
+
+use crate::mesh::*;
+use crate::tangents::*;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// All generators produce indexed triangle lists wound counter-clockwise when seen from
+// outside, with MikkTSpace tangents. Surfaces of revolution are built around the y axis
+// with u going around it and v running from the top down, like the original sphere.
+
+fn finish(mut vertices: Vec<MeshVertex>, indices: Vec<u32>) -> MeshData {
+    compute_mesh_tangents(&mut vertices, &triangle_list(&indices));
+    MeshData {
+        topology: gl::TRIANGLES,
+        vertices,
+        indices: Some(indices),
+    }
+}
+
+fn vertex(position: Vector3<f32>, uv: Vector2<f32>, normal: Vector3<f32>) -> MeshVertex {
+    MeshVertex {
+        position: position.into(),
+        uv: uv.into(),
+        normal: normal.into(),
+        tangent: [0.0; 4],
+    }
+}
+
+// Indices of a grid of (columns + 1) x (rows + 1) vertices laid out row by row, starting at
+// `first`. Rows follow v and columns follow u, so d/du x d/dv has to point outwards.
+fn grid_indices(first: u32, columns: u32, rows: u32, indices: &mut Vec<u32>) {
+    let index = |row: u32, column: u32| first + row * (columns + 1) + column;
+    for row in 0..rows {
+        for column in 0..columns {
+            indices.extend_from_slice(&[
+                index(row, column),
+                index(row, column + 1),
+                index(row + 1, column),
+                index(row, column + 1),
+                index(row + 1, column + 1),
+                index(row + 1, column),
+            ]);
+        }
+    }
+}
+
+// Revolves a profile of (radius, height) points with their (radial, vertical) normals
+// around the y axis. The profile goes from the top down and v follows its arc length.
+fn lathe(
+    profile: &[(Vector2<f32>, Vector2<f32>)],
+    segments: u32,
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<u32>,
+) {
+    assert!(
+        segments > 0,
+        "a surface of revolution needs at least one segment"
+    );
+    let mut lengths = vec![0.0];
+    for pair in profile.windows(2) {
+        let length = lengths.last().unwrap() + (pair[1].0 - pair[0].0).magnitude();
+        lengths.push(length);
+    }
+    let total = lengths.last().unwrap().max(f32::EPSILON);
+
+    let first = vertices.len() as u32;
+    for ((point, normal), length) in profile.iter().zip(&lengths) {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin, cos) = (u * 2.0 * PI).sin_cos();
+            vertices.push(vertex(
+                vec3(point.x * cos, point.y, point.x * sin),
+                vec2(u, length / total),
+                vec3(normal.x * cos, normal.y, normal.x * sin).normalize(),
+            ));
+        }
+    }
+    grid_indices(first, segments, profile.len() as u32 - 1, indices);
+}
+
+// A flat disk facing up or down, used to close cylinders and cones.
+fn cap(
+    radius: f32,
+    height: f32,
+    up: bool,
+    segments: u32,
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<u32>,
+) {
+    let (normal, flip) = if up {
+        (Vector3::unit_y(), -1.0)
+    } else {
+        (-Vector3::unit_y(), 1.0)
+    };
+    let center = vertices.len() as u32;
+    vertices.push(vertex(vec3(0.0, height, 0.0), vec2(0.5, 0.5), normal));
+    for segment in 0..=segments {
+        let (sin, cos) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+        vertices.push(vertex(
+            vec3(radius * cos, height, radius * sin),
+            vec2(0.5 + 0.5 * cos, 0.5 + 0.5 * flip * sin),
+            normal,
+        ));
+    }
+    for segment in 0..segments {
+        let (a, b) = (center + 1 + segment, center + 2 + segment);
+        if up {
+            indices.extend_from_slice(&[center, b, a]);
+        } else {
+            indices.extend_from_slice(&[center, a, b]);
+        }
+    }
+}
+
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    assert!(rings > 0, "a sphere needs at least one ring");
+    let profile = (0..=rings)
+        .map(|ring| {
+            let (sin, cos) = (ring as f32 / rings as f32 * PI).sin_cos();
+            (vec2(radius * sin, radius * cos), vec2(sin, cos))
+        })
+        .collect::<Vec<_>>();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&profile, segments, &mut vertices, &mut indices);
+    finish(vertices, indices)
+}
+
+// Same u, v mapping as uv_sphere, i.e. u = 0 along +x turning towards +z.
+fn sphere_uv(direction: Vector3<f32>) -> Vector2<f32> {
+    let u = direction.z.atan2(direction.x) / (2.0 * PI);
+    vec2(
+        if u < 0.0 { u + 1.0 } else { u },
+        direction.y.clamp(-1.0, 1.0).acos() / PI,
+    )
+}
+
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    #[rustfmt::skip]
+    let mut points = vec![
+        vec3(-1.0, t, 0.0), vec3(1.0, t, 0.0), vec3(-1.0, -t, 0.0), vec3(1.0, -t, 0.0),
+        vec3(0.0, -1.0, t), vec3(0.0, 1.0, t), vec3(0.0, -1.0, -t), vec3(0.0, 1.0, -t),
+        vec3(t, 0.0, -1.0), vec3(t, 0.0, 1.0), vec3(-t, 0.0, -1.0), vec3(-t, 0.0, 1.0),
+    ]
+    .into_iter()
+    .map(|p: Vector3<f32>| p.normalize())
+    .collect::<Vec<_>>();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let point = (points[a as usize] + points[b as usize]).normalize();
+                points.push(point);
+                points.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Triangles crossing the u seam get their low side shifted past 1, and pole vertices
+    // take the u of the rest of their triangle, so vertices are split per distinct u.
+    let is_pole = |point: Vector3<f32>| point.y.abs() > 1.0 - 1e-6;
+    let mut split = HashMap::new();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    for triangle in &triangles {
+        let mut uvs = triangle
+            .iter()
+            .map(|&i| sphere_uv(points[i as usize]))
+            .collect::<Vec<_>>();
+        let regular = (0..3)
+            .filter(|&k| !is_pole(points[triangle[k] as usize]))
+            .collect::<Vec<_>>();
+        let min_u = regular.iter().map(|&k| uvs[k].x).fold(1.0, f32::min);
+        let max_u = regular.iter().map(|&k| uvs[k].x).fold(0.0, f32::max);
+        if max_u - min_u > 0.5 {
+            for &k in &regular {
+                if uvs[k].x < 0.5 {
+                    uvs[k].x += 1.0;
+                }
+            }
+        }
+        let mean_u = regular.iter().map(|&k| uvs[k].x).sum::<f32>() / regular.len() as f32;
+        for k in 0..3 {
+            let point = points[triangle[k] as usize];
+            if is_pole(point) {
+                uvs[k].x = mean_u;
+            }
+            let index = *split
+                .entry((triangle[k], uvs[k].x.to_bits()))
+                .or_insert_with(|| {
+                    vertices.push(vertex(point * radius, uvs[k], point));
+                    vertices.len() as u32 - 1
+                });
+            indices.push(index);
+        }
+    }
+    finish(vertices, indices)
+}
+
+// A plane in xz facing up, with v growing towards -z.
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> MeshData {
+    assert!(
+        subdivisions_x > 0 && subdivisions_z > 0,
+        "a plane needs at least one subdivision along each axis"
+    );
+    let mut vertices = Vec::new();
+    for row in 0..=subdivisions_z {
+        for column in 0..=subdivisions_x {
+            let u = column as f32 / subdivisions_x as f32;
+            let v = row as f32 / subdivisions_z as f32;
+            vertices.push(vertex(
+                vec3(width * (u - 0.5), 0.0, depth * (0.5 - v)),
+                vec2(u, v),
+                Vector3::unit_y(),
+            ));
+        }
+    }
+    let mut indices = Vec::new();
+    grid_indices(0, subdivisions_x, subdivisions_z, &mut indices);
+    finish(vertices, indices)
+}
+
+// Each face gets its own four vertices with the full [0, 1] uv square.
+pub fn cuboid(half_extents: Vector3<f32>) -> MeshData {
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    for &normal in &[
+        Vector3::unit_x(),
+        -Vector3::unit_x(),
+        Vector3::unit_y(),
+        -Vector3::unit_y(),
+        Vector3::unit_z(),
+        -Vector3::unit_z(),
+    ] {
+        let up = if normal.y == 0.0 {
+            Vector3::unit_y()
+        } else {
+            -Vector3::unit_z() * normal.y
+        };
+        let right = up.cross(normal);
+        let first = vertices.len() as u32;
+        for &(u, v) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            let corner = normal + right * (2.0 * u - 1.0) + up * (2.0 * v - 1.0);
+            vertices.push(vertex(
+                corner.mul_element_wise(half_extents),
+                vec2(u, v),
+                normal,
+            ));
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 1, first + 3]);
+    }
+    finish(vertices, indices)
+}
+
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> MeshData {
+    assert!(sides > 0, "a torus needs at least one side");
+    // Starts at the outer equator and goes down first, so the normals face outwards.
+    let profile = (0..=sides)
+        .map(|side| {
+            let (sin, cos) = (side as f32 / sides as f32 * 2.0 * PI).sin_cos();
+            (
+                vec2(major_radius + minor_radius * cos, -minor_radius * sin),
+                vec2(cos, -sin),
+            )
+        })
+        .collect::<Vec<_>>();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&profile, segments, &mut vertices, &mut indices);
+    finish(vertices, indices)
+}
+
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let top = height / 2.0;
+    let profile = [
+        (vec2(radius, top), vec2(1.0, 0.0)),
+        (vec2(radius, -top), vec2(1.0, 0.0)),
+    ];
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&profile, segments, &mut vertices, &mut indices);
+    cap(radius, top, true, segments, &mut vertices, &mut indices);
+    cap(radius, -top, false, segments, &mut vertices, &mut indices);
+    finish(vertices, indices)
+}
+
+// The apex points up. Its ring is degenerate, which keeps a separate normal per segment.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    let top = height / 2.0;
+    let normal = vec2(height, radius).normalize();
+    let profile = [(vec2(0.0, top), normal), (vec2(radius, -top), normal)];
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&profile, segments, &mut vertices, &mut indices);
+    cap(radius, -top, false, segments, &mut vertices, &mut indices);
+    finish(vertices, indices)
+}
+
+// `height` is the length of the cylindrical part, the hemispheres add a radius on each end.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    assert!(rings > 0, "a capsule needs at least one ring");
+    let top = height / 2.0;
+    let hemisphere = |ring: u32, offset: f32| {
+        let (sin, cos) = (ring as f32 / (2 * rings) as f32 * PI).sin_cos();
+        (vec2(radius * sin, radius * cos + offset), vec2(sin, cos))
+    };
+    let profile = (0..=rings)
+        .map(|ring| hemisphere(ring, top))
+        .chain((rings..=2 * rings).map(|ring| hemisphere(ring, -top)))
+        .collect::<Vec<_>>();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&profile, segments, &mut vertices, &mut indices);
+    finish(vertices, indices)
+}
+
+// Turns a closed mesh inside out, e.g. for a skybox seen from within.
+pub fn inverted(mut data: MeshData) -> MeshData {
+    for vertex in &mut data.vertices {
+        vertex.normal = (-Vector3::from(vertex.normal)).into();
+        vertex.tangent[3] = -vertex.tangent[3];
+    }
+    if let Some(indices) = &mut data.indices {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(data: &MeshData, index: u32) -> Vector3<f32> {
+        Vector3::from(data.vertices[index as usize].position)
+    }
+
+    fn normal(data: &MeshData, index: u32) -> Vector3<f32> {
+        Vector3::from(data.vertices[index as usize].normal)
+    }
+
+    fn check_counts(data: &MeshData, vertex_count: usize, index_count: usize) {
+        assert_eq!(data.topology, gl::TRIANGLES);
+        assert_eq!(data.vertices.len(), vertex_count);
+        let indices = data.indices.as_ref().unwrap();
+        assert_eq!(indices.len(), index_count);
+        assert!(indices.iter().all(|&i| (i as usize) < vertex_count));
+    }
+
+    // `centre` gives the point of the surface's interior closest to a vertex.
+    fn check_normals(data: &MeshData, centre: impl Fn(Vector3<f32>) -> Vector3<f32>) {
+        for vertex in &data.vertices {
+            let (p, n) = (Vector3::from(vertex.position), Vector3::from(vertex.normal));
+            assert!((n.magnitude() - 1.0).abs() < 1e-4, "{:?} is not unit", n);
+            assert!(
+                n.dot(p - centre(p)) > 0.0,
+                "{:?} at {:?} points inwards",
+                n,
+                p
+            );
+        }
+    }
+
+    // Triangles seen from the side their vertex normals point to must be counter-clockwise.
+    fn check_winding(data: &MeshData) {
+        for triangle in data.indices.as_ref().unwrap().chunks_exact(3) {
+            let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+            let face = (position(data, b) - position(data, a))
+                .cross(position(data, c) - position(data, a));
+            if face.magnitude() < 1e-6 {
+                // Pole and apex rings are degenerate.
+                continue;
+            }
+            let vertex_normals = normal(data, a) + normal(data, b) + normal(data, c);
+            assert!(
+                face.dot(vertex_normals) > 0.0,
+                "triangle {:?} is wound against its normals",
+                triangle
+            );
+        }
+    }
+
+    fn origin(_: Vector3<f32>) -> Vector3<f32> {
+        Vector3::zero()
+    }
+
+    #[test]
+    fn uv_sphere_geometry() {
+        let data = uv_sphere(2.0, 16, 8);
+        check_counts(&data, 17 * 9, 6 * 16 * 8);
+        check_normals(&data, origin);
+        check_winding(&data);
+        for vertex in &data.vertices {
+            assert!((Vector3::from(vertex.position).magnitude() - 2.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn icosphere_subdivision_counts() {
+        for subdivisions in 0..4 {
+            let data = icosphere(1.5, subdivisions);
+            let triangles = 20 * 4usize.pow(subdivisions);
+            assert_eq!(data.indices.as_ref().unwrap().len(), 3 * triangles);
+            assert!(data
+                .indices
+                .as_ref()
+                .unwrap()
+                .iter()
+                .all(|&i| (i as usize) < data.vertices.len()));
+
+            // Seam and pole vertices are split, the distinct positions follow Euler's formula.
+            let mut positions = data
+                .vertices
+                .iter()
+                .map(|v| {
+                    v.position
+                        .iter()
+                        .map(|c| (c * 1e4).round() as i32)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            positions.sort();
+            positions.dedup();
+            assert_eq!(positions.len(), 10 * 4usize.pow(subdivisions) + 2);
+
+            check_normals(&data, origin);
+            check_winding(&data);
+        }
+    }
+
+    #[test]
+    fn plane_geometry() {
+        let data = plane(4.0, 2.0, 3, 2);
+        check_counts(&data, 4 * 3, 6 * 3 * 2);
+        check_normals(&data, |p| p - Vector3::unit_y());
+        check_winding(&data);
+    }
+
+    #[test]
+    fn cuboid_geometry() {
+        let data = cuboid(vec3(1.0, 2.0, 3.0));
+        check_counts(&data, 24, 36);
+        check_normals(&data, origin);
+        check_winding(&data);
+    }
+
+    #[test]
+    fn torus_geometry() {
+        let data = torus(2.0, 0.5, 24, 12);
+        check_counts(&data, 25 * 13, 6 * 24 * 12);
+        // Normals point away from the circle through the middle of the tube.
+        check_normals(&data, |p| vec3(p.x, 0.0, p.z).normalize() * 2.0);
+        check_winding(&data);
+    }
+
+    #[test]
+    fn cylinder_geometry() {
+        let data = cylinder(1.0, 3.0, 16);
+        check_counts(&data, 2 * 17 + 2 * 18, 6 * 16 + 2 * 3 * 16);
+        check_normals(&data, origin);
+        check_winding(&data);
+    }
+
+    #[test]
+    fn cone_geometry() {
+        let data = cone(1.0, 2.0, 16);
+        check_counts(&data, 2 * 17 + 18, 6 * 16 + 3 * 16);
+        check_normals(&data, origin);
+        check_winding(&data);
+    }
+
+    #[test]
+    fn capsule_geometry() {
+        let data = capsule(0.5, 2.0, 16, 4);
+        check_counts(&data, 10 * 17, 6 * 16 * 9);
+        check_normals(&data, |p| vec3(0.0, p.y.clamp(-1.0, 1.0), 0.0));
+        check_winding(&data);
+    }
+
+    #[test]
+    fn inverted_flips_normals_and_winding() {
+        let data = inverted(cuboid(vec3(1.0, 1.0, 1.0)));
+        check_counts(&data, 24, 36);
+        check_normals(&data, |p| p * 2.0);
+        check_winding(&data);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one segment")]
+    fn zero_segments_are_rejected() {
+        uv_sphere(1.0, 0, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one ring")]
+    fn zero_rings_are_rejected() {
+        uv_sphere(1.0, 8, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one subdivision")]
+    fn zero_plane_subdivisions_are_rejected() {
+        plane(1.0, 1.0, 0, 1);
+    }
+}
@@ -0,0 +1,551 @@
+extern crate cgmath;
+
+use cgmath::*;
+use std::cell::RefCell;
+
+use crate::animation::*;
+use crate::buffers::*;
+use crate::frustum::*;
+use crate::gltf_loader::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::obj_loader::*;
+use crate::shaders::*;
+use crate::utils::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl NodeTransform {
+    pub fn identity() -> Self {
+        Self {
+            translation: vec3(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    pub fn to_matrix(self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    // Shines in all directions from the node's position.
+    Point,
+    // Shines along the node's -z axis.
+    Directional,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+// Meshes are owned by the graph, nodes refer to them by index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeMesh {
+    Mesh(usize),
+    Instanced(usize),
+    Gltf(usize),
+}
+
+// A mesh drawn with a single instanced call for all of its visible nodes, which needs a
+// shader reading PbrInstance attributes like shaders/sphere_pbr_instanced.vert.
+pub struct InstancedMesh {
+    pub mesh: Mesh,
+    // Rewritten by every draw of the graph.
+    instance_buffer: RefCell<InstanceBuffer>,
+}
+
+pub struct SceneNode {
+    pub name: Option<String>,
+    transform: NodeTransform,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    world_transform: Matrix4<f32>,
+    dirty: bool,
+    pub mesh: Option<NodeMesh>,
+    // Overrides the materials of glTF primitives, plain meshes need one to not use the
+    // default material.
+    pub material: Option<usize>,
+    pub light: Option<Light>,
+    pub skin: Option<usize>,
+    // Morph target weights of a glTF mesh, driven by animations or set directly.
+    pub weights: Vec<f32>,
+}
+
+impl SceneNode {
+    fn new(name: Option<String>, transform: NodeTransform, parent: Option<usize>) -> Self {
+        Self {
+            name,
+            transform,
+            parent,
+            children: Vec::new(),
+            world_transform: Matrix4::identity(),
+            dirty: true,
+            mesh: None,
+            material: None,
+            light: None,
+            skin: None,
+            weights: Vec::new(),
+        }
+    }
+
+    pub fn transform(&self) -> &NodeTransform {
+        &self.transform
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+
+    // Only up to date after SceneGraph::update_world_transforms.
+    pub fn world_transform(&self) -> &Matrix4<f32> {
+        &self.world_transform
+    }
+}
+
+// Nodes with local transforms, each caching its world transform until it or one of its
+// ancestors changes. Imported glTF and OBJ files become subtrees of the graph.
+pub struct SceneGraph {
+    nodes: Vec<SceneNode>,
+    roots: Vec<usize>,
+    pub meshes: Vec<Mesh>,
+    pub instanced_meshes: Vec<InstancedMesh>,
+    pub gltf_meshes: Vec<GltfMesh>,
+    pub materials: Vec<Material>,
+    // Used by nodes and primitives without a material.
+    pub default_material: Material,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<Animation>,
+    skin_block: UniformBuffer<SkinBlock>,
+}
+
+impl SceneGraph {
+    // Matches MAX_LIGHTS of shaders/gltf_pbr_ibl.frag.
+    pub const MAX_LIGHTS: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            meshes: Vec::new(),
+            instanced_meshes: Vec::new(),
+            gltf_meshes: Vec::new(),
+            materials: Vec::new(),
+            default_material: Material::default(),
+            skins: Vec::new(),
+            animations: Vec::new(),
+            skin_block: UniformBuffer::new(),
+        }
+    }
+
+    pub fn add_node(
+        &mut self,
+        parent: Option<usize>,
+        name: Option<String>,
+        transform: NodeTransform,
+    ) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(SceneNode::new(name, transform, parent));
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(index),
+            None => self.roots.push(index),
+        }
+        index
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    // `capacity` is the most nodes which can use the mesh.
+    pub fn add_instanced_mesh(&mut self, mesh: Mesh, capacity: usize) -> usize {
+        let instance_buffer = RefCell::new(InstanceBuffer::new::<PbrInstance>(
+            mesh.vertex_array(),
+            capacity,
+        ));
+        self.instanced_meshes.push(InstancedMesh {
+            mesh,
+            instance_buffer,
+        });
+        self.instanced_meshes.len() - 1
+    }
+
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    pub fn nodes(&self) -> &[SceneNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, node: usize) -> &SceneNode {
+        &self.nodes[node]
+    }
+
+    pub fn node_mut(&mut self, node: usize) -> &mut SceneNode {
+        &mut self.nodes[node]
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    pub fn set_transform(&mut self, node: usize, transform: NodeTransform) {
+        self.nodes[node].transform = transform;
+        self.mark_dirty(node);
+    }
+
+    // Marks the node dirty, as the caller is expected to change the transform.
+    pub fn transform_mut(&mut self, node: usize) -> &mut NodeTransform {
+        self.mark_dirty(node);
+        &mut self.nodes[node].transform
+    }
+
+    pub fn set_parent(&mut self, node: usize, parent: Option<usize>) -> Result<(), String> {
+        let mut ancestor = parent;
+        while let Some(index) = ancestor {
+            if index == node {
+                return Err(format!("Node {} can't be parented to its descendant", node));
+            }
+            ancestor = self.nodes[index].parent;
+        }
+        match self.nodes[node].parent {
+            Some(old) => self.nodes[old].children.retain(|child| *child != node),
+            None => self.roots.retain(|root| *root != node),
+        }
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(node),
+            None => self.roots.push(node),
+        }
+        self.nodes[node].parent = parent;
+        self.mark_dirty(node);
+        Ok(())
+    }
+
+    // A dirty node always has dirty descendants, so already dirty subtrees are skipped.
+    fn mark_dirty(&mut self, node: usize) {
+        let mut stack = vec![node];
+        while let Some(index) = stack.pop() {
+            if self.nodes[index].dirty && index != node {
+                continue;
+            }
+            self.nodes[index].dirty = true;
+            stack.extend_from_slice(&self.nodes[index].children);
+        }
+    }
+
+    pub fn update_world_transforms(&mut self) {
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|root| (Matrix4::identity(), *root))
+            .collect::<Vec<_>>();
+        while let Some((parent_transform, index)) = stack.pop() {
+            let node = &mut self.nodes[index];
+            if node.dirty {
+                node.world_transform = parent_transform * node.transform.to_matrix();
+                node.dirty = false;
+            }
+            let transform = node.world_transform;
+            stack.extend(node.children.iter().map(|child| (transform, *child)));
+        }
+    }
+
+    // Depth-first order, parents before their children.
+    pub fn traverse(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = self.roots.iter().rev().cloned().collect::<Vec<_>>();
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(self.nodes[index].children.iter().rev());
+        }
+        order
+    }
+
+    pub fn lights(&self) -> Vec<(&Matrix4<f32>, &Light)> {
+        self.traverse()
+            .into_iter()
+            .filter_map(|index| {
+                let node = &self.nodes[index];
+                Some((&node.world_transform, node.light.as_ref()?))
+            })
+            .collect()
+    }
+
+    // Sets the "light_*" uniforms of shaders/gltf_pbr_ibl.frag from the first
+    // MAX_LIGHTS lights. Point lights pass their position with w = 1, directional ones
+    // the direction they shine in with w = 0.
+    pub fn bind_lights(&self, shader: &Shader) {
+        let lights = self.lights();
+        let count = lights.len().min(Self::MAX_LIGHTS);
+        shader.set_uniform_1i("light_count", &(count as i32));
+        for (i, (transform, light)) in lights.into_iter().take(count).enumerate() {
+            let vector = match light.kind {
+                LightKind::Point => transform.w,
+                LightKind::Directional => (-transform.z.truncate()).normalize().extend(0.0),
+            };
+            shader.set_uniform_4f(&format!("light_vectors[{}]", i), &vector);
+            shader.set_uniform_3f(
+                &format!("light_colors[{}]", i),
+                &(light.color * light.intensity),
+            );
+        }
+    }
+
+    // Adds the glTF nodes under a new node, taking over its meshes, materials, skins
+    // and animations.
+    pub fn import_gltf(&mut self, scene: GltfScene, parent: Option<usize>) -> usize {
+        let root = self.add_node(parent, None, NodeTransform::identity());
+        let node_offset = self.nodes.len();
+        let mesh_offset = self.gltf_meshes.len();
+        let material_offset = self.materials.len();
+        let skin_offset = self.skins.len();
+
+        for node in scene.nodes {
+            let mut graph_node = SceneNode::new(
+                node.name,
+                NodeTransform {
+                    translation: node.translation,
+                    rotation: node.rotation,
+                    scale: node.scale,
+                },
+                None,
+            );
+            graph_node.children = node
+                .children
+                .iter()
+                .map(|child| node_offset + child)
+                .collect();
+            graph_node.mesh = node.mesh.map(|mesh| NodeMesh::Gltf(mesh_offset + mesh));
+            graph_node.skin = node.skin.map(|skin| skin_offset + skin);
+            graph_node.weights = node.weights;
+            self.nodes.push(graph_node);
+        }
+        // glTF nodes list their children, which may come before them.
+        for index in node_offset..self.nodes.len() {
+            for child in self.nodes[index].children.clone() {
+                self.nodes[child].parent = Some(index);
+            }
+        }
+        for gltf_root in scene.roots {
+            self.nodes[node_offset + gltf_root].parent = Some(root);
+            self.nodes[root].children.push(node_offset + gltf_root);
+        }
+
+        self.gltf_meshes
+            .extend(scene.meshes.into_iter().map(|mut mesh| {
+                for primitive in &mut mesh.primitives {
+                    primitive.material = primitive.material.map(|m| material_offset + m);
+                }
+                mesh
+            }));
+        self.materials.extend(scene.materials);
+        self.skins.extend(scene.skins.into_iter().map(|mut skin| {
+            for joint in &mut skin.joints {
+                *joint += node_offset;
+            }
+            skin
+        }));
+        self.animations
+            .extend(scene.animations.into_iter().map(|mut animation| {
+                for channel in &mut animation.channels {
+                    channel.node += node_offset;
+                }
+                animation
+            }));
+        root
+    }
+
+    // Adds a node with one child per submesh.
+    pub fn import_obj(&mut self, model: ObjModel, parent: Option<usize>) -> usize {
+        let root = self.add_node(parent, None, NodeTransform::identity());
+        let material_offset = self.materials.len();
+        self.materials.extend(model.materials);
+        for submesh in model.submeshes {
+            let node = self.add_node(Some(root), submesh.name, NodeTransform::identity());
+            let mesh = self.add_mesh(submesh.mesh);
+            self.nodes[node].mesh = Some(NodeMesh::Mesh(mesh));
+            self.nodes[node].material = submesh.material.map(|m| material_offset + m);
+        }
+        root
+    }
+
+    // Poses the nodes targeted by `animation` at `time` seconds.
+    pub fn animate(&mut self, animation: usize, time: f32) {
+        let mut moved = Vec::new();
+        for channel in &self.animations[animation].channels {
+            let node = &mut self.nodes[channel.node];
+            match channel.property {
+                AnimationProperty::Translation => {
+                    let value = channel.sampler.sample(time);
+                    node.transform.translation = vec3(value[0], value[1], value[2]);
+                }
+                AnimationProperty::Rotation => {
+                    node.transform.rotation = channel.sampler.sample_rotation(time);
+                }
+                AnimationProperty::Scale => {
+                    let value = channel.sampler.sample(time);
+                    node.transform.scale = vec3(value[0], value[1], value[2]);
+                }
+                AnimationProperty::Weights => {
+                    node.weights = channel.sampler.sample(time);
+                    continue;
+                }
+            }
+            moved.push(channel.node);
+        }
+        for node in moved {
+            self.mark_dirty(node);
+        }
+    }
+
+    fn material(&self, material: Option<usize>) -> &Material {
+        material.map_or(&self.default_material, |index| &self.materials[index])
+    }
+
+    // Draws every mesh in the graph with `shader`, setting its "model" uniform and
+    // binding the materials. Graphs with skins also set the "skinned" uniform and the
+    // Skin block, graphs with morph targets the uniforms of shaders/morph.glsl. Both are
+    // handled by shaders/sphere_pbr_skinned.vert.
+    // Meshes outside of `frustum` are skipped, except for skinned and morphed ones
+    // whose rest pose bounds say little about where they end up.
+    // The visible nodes of an instanced mesh are drawn after the others, all with the
+    // material of the first one except for their own metallic and roughness factors.
+    pub fn draw(&self, shader: &Shader, frustum: &Frustum) -> CullStats {
+        let morphing = self
+            .gltf_meshes
+            .iter()
+            .any(|mesh| mesh.morph_target_count > 0);
+        self.bind_lights(shader);
+        let mut stats = CullStats::default();
+        let mut instanced_nodes = vec![Vec::new(); self.instanced_meshes.len()];
+        for index in self.traverse() {
+            let node = &self.nodes[index];
+            let model = &node.world_transform;
+            match node.mesh {
+                Some(NodeMesh::Instanced(mesh)) => {
+                    let mesh_data = &self.instanced_meshes[mesh].mesh;
+                    if stats.record(frustum.is_visible(
+                        mesh_data.bounds(),
+                        mesh_data.bounding_sphere(),
+                        model,
+                    )) {
+                        instanced_nodes[mesh].push(index);
+                    }
+                }
+                Some(NodeMesh::Mesh(mesh)) => {
+                    let mesh = &self.meshes[mesh];
+                    if !stats.record(frustum.is_visible(
+                        mesh.bounds(),
+                        mesh.bounding_sphere(),
+                        model,
+                    )) {
+                        continue;
+                    }
+                    if !self.skins.is_empty() {
+                        shader.set_uniform_1i("skinned", &0);
+                    }
+                    if morphing {
+                        shader.set_uniform_1i("morph_target_count", &0);
+                    }
+                    shader.set_uniform_mat4f("model", model);
+                    self.material(node.material).bind(shader);
+                    mesh.draw();
+                }
+                Some(NodeMesh::Gltf(mesh)) => {
+                    let primitives = self.gltf_meshes[mesh]
+                        .primitives
+                        .iter()
+                        .filter(|primitive| {
+                            let (bounds, sphere) = primitive.bounds();
+                            stats.record(
+                                node.skin.is_some()
+                                    || primitive.has_morph_targets()
+                                    || frustum.is_visible(bounds, sphere, model),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    if primitives.is_empty() {
+                        continue;
+                    }
+                    if !self.skins.is_empty() {
+                        shader.set_uniform_1i("skinned", &(node.skin.is_some() as i32));
+                    }
+                    if let Some(skin) = node.skin {
+                        self.skin_block.update(
+                            &self.skins[skin]
+                                .to_block(model, |joint| self.nodes[joint].world_transform),
+                        );
+                        self.skin_block.bind();
+                    }
+                    shader.set_uniform_mat4f("model", model);
+                    for primitive in primitives {
+                        self.material(node.material.or(primitive.material))
+                            .bind(shader);
+                        if morphing {
+                            primitive.bind_morph_targets(shader, &node.weights);
+                        }
+                        primitive.draw();
+                    }
+                }
+                None => (),
+            }
+        }
+
+        for (instanced, nodes) in self.instanced_meshes.iter().zip(&instanced_nodes) {
+            let first = match nodes.first() {
+                Some(first) => &self.nodes[*first],
+                None => continue,
+            };
+            let instances = nodes
+                .iter()
+                .map(|index| {
+                    let node = &self.nodes[*index];
+                    let material = self.material(node.material);
+                    PbrInstance {
+                        model: node.world_transform.into(),
+                        metallic: material.metallic_factor,
+                        roughness: material.roughness_factor,
+                    }
+                })
+                .collect::<Vec<_>>();
+            self.material(first.material).bind(shader);
+            // The instances scale these by their own factors.
+            shader.set_uniform_1f("metallic_factor", &1.0);
+            shader.set_uniform_1f("roughness_factor", &1.0);
+            draw_mesh_instanced(
+                &instanced.mesh,
+                &mut instanced.instance_buffer.borrow_mut(),
+                &instances,
+            );
+        }
+        stats
+    }
+}
@@ -0,0 +1,280 @@
+extern crate exr;
+extern crate gl;
+extern crate image;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image::*;
+
+use crate::buffers::*;
+
+pub const SCREENSHOT_DIRECTORY: &str = "screenshots";
+
+// Mirrors the Output block in shaders/tonemap.glsl.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct OutputBlock {
+    pub linear_output: i32,
+    _padding: [i32; 3],
+}
+
+impl OutputBlock {
+    pub fn new(linear_output: bool) -> Self {
+        Self {
+            linear_output: linear_output as i32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+impl UniformBlock for OutputBlock {
+    const LAYOUT: UniformBlockLayout = UniformBlockLayout {
+        name: "Output",
+        binding: 2,
+        size: std::mem::size_of::<OutputBlock>(),
+        members: &[("linear_output", 0)],
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScreenshotFormat {
+    Png,
+    // Radiance .hdr and OpenEXR, both hold the linear scene colors before tone mapping.
+    Hdr,
+    Exr,
+}
+
+impl ScreenshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Hdr => "hdr",
+            ScreenshotFormat::Exr => "exr",
+        }
+    }
+
+    // Captured from a float render target drawn with linear output.
+    pub fn is_float(&self) -> bool {
+        *self != ScreenshotFormat::Png
+    }
+}
+
+pub fn current_draw_framebuffer() -> gl::types::GLuint {
+    let mut id = 0;
+    unsafe {
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut id);
+    }
+    id as gl::types::GLuint
+}
+
+fn read_pixels(
+    framebuffer: gl::types::GLuint,
+    size: (u32, u32),
+    data_type: gl::types::GLenum,
+    pixels: *mut std::ffi::c_void,
+) {
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        if framebuffer == 0 {
+            gl::ReadBuffer(gl::BACK);
+        } else {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            size.0 as i32,
+            size.1 as i32,
+            gl::RGB,
+            data_type,
+            pixels,
+        );
+    }
+}
+
+pub fn read_pixels_rgb8(framebuffer: gl::types::GLuint, size: (u32, u32)) -> RgbImage {
+    let mut pixels = vec![0u8; (size.0 * size.1 * 3) as usize];
+    read_pixels(
+        framebuffer,
+        size,
+        gl::UNSIGNED_BYTE,
+        pixels.as_mut_ptr() as *mut std::ffi::c_void,
+    );
+    let image = RgbImage::from_raw(size.0, size.1, pixels).unwrap();
+    imageops::flip_vertical(&image)
+}
+
+pub fn read_pixels_rgb32f(
+    framebuffer: gl::types::GLuint,
+    size: (u32, u32),
+) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+    let mut pixels = vec![0.0f32; (size.0 * size.1 * 3) as usize];
+    read_pixels(
+        framebuffer,
+        size,
+        gl::FLOAT,
+        pixels.as_mut_ptr() as *mut std::ffi::c_void,
+    );
+    let image = ImageBuffer::<Rgb<f32>, Vec<f32>>::from_raw(size.0, size.1, pixels).unwrap();
+    imageops::flip_vertical(&image)
+}
+
+pub fn save_hdr(image: &ImageBuffer<Rgb<f32>, Vec<f32>>, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|_| format!("Cannot create: {}", path.display()))?;
+    let pixels = image.pixels().cloned().collect::<Vec<Rgb<f32>>>();
+    hdr::HDREncoder::new(BufWriter::new(file))
+        .encode(&pixels, image.width() as usize, image.height() as usize)
+        .map_err(|_| format!("Cannot encode HDR image: {}", path.display()))
+}
+
+// image 0.22 has no OpenEXR encoder, so the exr crate writes these.
+pub fn save_exr(image: &ImageBuffer<Rgb<f32>, Vec<f32>>, path: &Path) -> Result<(), String> {
+    exr::prelude::write_rgb_file(
+        path,
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| {
+            let pixel = image.get_pixel(x as u32, y as u32);
+            (pixel[0], pixel[1], pixel[2])
+        },
+    )
+    .map_err(|_| format!("Cannot encode EXR image: {}", path.display()))
+}
+
+pub fn scene_file_stem(scene_name: &str) -> String {
+    scene_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// `timestamp` is the time since the Unix epoch.
+fn screenshot_path(scene_name: &str, format: ScreenshotFormat, timestamp: Duration) -> PathBuf {
+    Path::new(SCREENSHOT_DIRECTORY).join(format!(
+        "{}_{}_{:03}.{}",
+        scene_file_stem(scene_name),
+        timestamp.as_secs(),
+        timestamp.subsec_millis(),
+        format.extension()
+    ))
+}
+
+pub fn save_screenshot(
+    framebuffer: gl::types::GLuint,
+    size: (u32, u32),
+    scene_name: &str,
+    format: ScreenshotFormat,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(SCREENSHOT_DIRECTORY)
+        .map_err(|_| format!("Cannot create directory: {}", SCREENSHOT_DIRECTORY))?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = screenshot_path(scene_name, format, timestamp);
+    match format {
+        ScreenshotFormat::Png => read_pixels_rgb8(framebuffer, size)
+            .save(&path)
+            .map_err(|_| format!("Cannot write: {}", path.display()))?,
+        ScreenshotFormat::Hdr => save_hdr(&read_pixels_rgb32f(framebuffer, size), &path)?,
+        ScreenshotFormat::Exr => save_exr(&read_pixels_rgb32f(framebuffer, size), &path)?,
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("screenshots_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    }
+
+    // Includes values above 1, which only survive in the float formats.
+    fn float_image() -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+        ImageBuffer::from_fn(4, 2, |x, y| Rgb([x as f32 * 2.0, y as f32, 0.25]))
+    }
+
+    #[test]
+    fn scene_file_stems() {
+        let cases = [
+            ("PBR Spheres", "pbr_spheres"),
+            ("PBR Textured Spheres", "pbr_textured_spheres"),
+            ("Animated Model (skinned)", "animated_model__skinned_"),
+            ("Café-1", "caf__1"),
+            ("", ""),
+        ];
+        for (name, stem) in cases.iter() {
+            assert_eq!(scene_file_stem(name), *stem);
+        }
+    }
+
+    #[test]
+    fn screenshot_paths() {
+        let timestamp = Duration::new(1_700_000_000, 42_500_000);
+        let cases = [
+            (ScreenshotFormat::Png, "pbr_glock_1700000000_042.png"),
+            (ScreenshotFormat::Hdr, "pbr_glock_1700000000_042.hdr"),
+            (ScreenshotFormat::Exr, "pbr_glock_1700000000_042.exr"),
+        ];
+        for (format, file_name) in cases.iter() {
+            assert_eq!(
+                screenshot_path("PBR Glock", *format, timestamp),
+                Path::new(SCREENSHOT_DIRECTORY).join(file_name)
+            );
+        }
+        // Milliseconds are zero padded so the names sort by time.
+        assert_eq!(
+            screenshot_path("a", ScreenshotFormat::Png, Duration::new(5, 7_000_000)),
+            Path::new(SCREENSHOT_DIRECTORY).join("a_5_007.png")
+        );
+    }
+
+    #[test]
+    fn float_formats() {
+        assert!(!ScreenshotFormat::Png.is_float());
+        assert!(ScreenshotFormat::Hdr.is_float());
+        assert!(ScreenshotFormat::Exr.is_float());
+    }
+
+    #[test]
+    fn hdr_keeps_values_above_one() {
+        let path = temp_path("values.hdr");
+        save_hdr(&float_image(), &path).unwrap();
+        let decoder =
+            hdr::HDRDecoder::new(std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+        let metadata = decoder.metadata();
+        assert_eq!((metadata.width, metadata.height), (4, 2));
+        let pixels = decoder.read_image_hdr().unwrap();
+        // Radiance files store 8 bit mantissas with a shared exponent.
+        for (actual, expected) in pixels.iter().zip(float_image().pixels()) {
+            for c in 0..3 {
+                assert!(
+                    (actual[c] - expected[c]).abs() <= expected[c] / 64.0 + 1e-3,
+                    "{:?} != {:?}",
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exr_is_written() {
+        let path = temp_path("values.exr");
+        save_exr(&float_image(), &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+    }
+}
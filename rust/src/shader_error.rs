@@ -0,0 +1,387 @@
+extern crate gl;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+// A single driver log entry, mapped back to the file it came from through the
+// source string numbers of the #line directives.
+#[derive(Clone, Debug)]
+pub struct ShaderMessage {
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => {
+                write!(f, "{}:{}: {}: {}", file, line, severity, self.message)
+            }
+            (Some(file), None) => write!(f, "{}: {}: {}", file, severity, self.message),
+            _ => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShaderErrorKind {
+    Source,
+    Compile,
+    Link,
+    InvalidStages,
+    // A stage needs a newer OpenGL version than the context provides.
+    Unsupported,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    pub kind: ShaderErrorKind,
+    pub stage: Option<gl::types::GLenum>,
+    pub path: Option<String>,
+    pub messages: Vec<ShaderMessage>,
+}
+
+impl ShaderError {
+    pub fn new(kind: ShaderErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            stage: None,
+            path: None,
+            messages: vec![ShaderMessage {
+                severity: Severity::Error,
+                file: None,
+                line: None,
+                message,
+            }],
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ShaderMessage> {
+        self.messages
+            .iter()
+            .filter(|m| m.severity == Severity::Error)
+    }
+}
+
+pub fn stage_name(shader_type: gl::types::GLenum) -> &'static str {
+    match shader_type {
+        gl::VERTEX_SHADER => "vertex",
+        gl::TESS_CONTROL_SHADER => "tessellation control",
+        gl::TESS_EVALUATION_SHADER => "tessellation evaluation",
+        gl::GEOMETRY_SHADER => "geometry",
+        gl::FRAGMENT_SHADER => "fragment",
+        gl::COMPUTE_SHADER => "compute",
+        _ => "unknown",
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.kind {
+            ShaderErrorKind::Source => "Failed to load",
+            ShaderErrorKind::Compile => "Failed to compile",
+            ShaderErrorKind::Link => "Failed to link",
+            ShaderErrorKind::InvalidStages => "Invalid stages for",
+            ShaderErrorKind::Unsupported => "Unsupported",
+        };
+        match self.stage {
+            Some(stage) => write!(f, "{} {} shader", action, stage_name(stage))?,
+            None => write!(f, "{} shader program", action)?,
+        }
+        if let Some(path) = &self.path {
+            write!(f, " {}", path)?;
+        }
+        for message in &self.messages {
+            write!(f, "\n  {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<ShaderError> for String {
+    fn from(error: ShaderError) -> Self {
+        error.to_string()
+    }
+}
+
+fn parse_number_prefix(s: &str) -> Option<(u32, &str)> {
+    let digits = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+    Some((s[..digits].parse().ok()?, &s[digits..]))
+}
+
+// Recognizes the location prefixes used by the common drivers:
+//   Mesa:           "0:12(5): error: ..."
+//   NVIDIA:         "0(12) : error C1008: ..."
+//   AMD, Intel:     "ERROR: 0:12: ..."
+fn parse_location(s: &str) -> Option<(u32, u32, &str)> {
+    let s = s.trim_start();
+    let (source, rest) = parse_number_prefix(s)?;
+    if let Some(rest) = rest.strip_prefix(':') {
+        let (line, rest) = parse_number_prefix(rest)?;
+        let rest = match rest.strip_prefix('(') {
+            Some(column) => &column[column.find(')')? + 1..],
+            None => rest,
+        };
+        return Some((source, line, rest.trim_start().strip_prefix(':')?));
+    }
+    let rest = rest.strip_prefix('(')?;
+    let (line, rest) = parse_number_prefix(rest)?;
+    let rest = rest.strip_prefix(')')?.trim_start().strip_prefix(':')?;
+    Some((source, line, rest))
+}
+
+fn parse_severity(s: &str) -> (Option<Severity>, &str) {
+    let trimmed = s.trim_start();
+    let lowercase = trimmed.to_ascii_lowercase();
+    for (prefix, severity) in &[
+        ("error", Severity::Error),
+        ("warning", Severity::Warning),
+        ("info", Severity::Info),
+    ] {
+        if lowercase.starts_with(prefix) {
+            let rest = &trimmed[prefix.len()..];
+            let rest = match rest.strip_prefix(':') {
+                Some(rest) => rest,
+                // NVIDIA puts a code like "C1008:" between the severity and the message.
+                None => match rest.trim_start().split_once(':') {
+                    Some((code, message)) if !code.is_empty() && !code.contains(' ') => message,
+                    _ => rest,
+                },
+            };
+            return (Some(*severity), rest.trim());
+        }
+    }
+    (None, trimmed)
+}
+
+pub fn parse_info_log(log: &str, files: &[String]) -> Vec<ShaderMessage> {
+    log.lines()
+        .map(|line| line.trim_end_matches('\0').trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (prefix_severity, rest) = parse_severity(line);
+            match parse_location(rest) {
+                Some((source, line_number, rest)) => {
+                    let (severity, message) = parse_severity(rest);
+                    ShaderMessage {
+                        severity: prefix_severity.or(severity).unwrap_or(Severity::Error),
+                        file: files.get(source as usize).cloned(),
+                        line: Some(line_number),
+                        message: message.to_string(),
+                    }
+                }
+                None => ShaderMessage {
+                    severity: prefix_severity.unwrap_or(Severity::Info),
+                    file: None,
+                    line: None,
+                    message: rest.to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Input line and the expected result.
+    type LocationCase = (&'static str, Option<(u32, u32, &'static str)>);
+    // Input line, then the expected severity, file, line and message.
+    type InfoLogCase = (
+        &'static str,
+        Severity,
+        Option<&'static str>,
+        Option<u32>,
+        &'static str,
+    );
+
+    #[test]
+    fn parse_locations() {
+        let cases: &[LocationCase] = &[
+            // Mesa
+            (
+                "0:12(5): error: `foo' undeclared",
+                Some((0, 12, " error: `foo' undeclared")),
+            ),
+            ("2:7(18): warning: unused", Some((2, 7, " warning: unused"))),
+            // NVIDIA
+            (
+                "0(12) : error C1008: undefined variable \"foo\"",
+                Some((0, 12, " error C1008: undefined variable \"foo\"")),
+            ),
+            // AMD and Intel, after the severity prefix
+            (
+                "1:40: 'foo' : undeclared identifier",
+                Some((1, 40, " 'foo' : undeclared identifier")),
+            ),
+            ("error: no location", None),
+            ("0:12 missing colon", None),
+            ("0(12 : unterminated", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_location(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_severities() {
+        let cases: &[(&str, Option<Severity>, &str)] = &[
+            (
+                " error: `foo' undeclared",
+                Some(Severity::Error),
+                "`foo' undeclared",
+            ),
+            (
+                " warning: unused variable",
+                Some(Severity::Warning),
+                "unused variable",
+            ),
+            (
+                " error C1008: undefined variable \"foo\"",
+                Some(Severity::Error),
+                "undefined variable \"foo\"",
+            ),
+            (
+                "warning C7050: \"x\" might be used before being initialized",
+                Some(Severity::Warning),
+                "\"x\" might be used before being initialized",
+            ),
+            (
+                "ERROR: 0:12: 'foo' : undeclared",
+                Some(Severity::Error),
+                "0:12: 'foo' : undeclared",
+            ),
+            (
+                "WARNING: 0:3: extension not supported",
+                Some(Severity::Warning),
+                "0:3: extension not supported",
+            ),
+            (
+                "INFO: shader compiled",
+                Some(Severity::Info),
+                "shader compiled",
+            ),
+            (
+                "'foo' : undeclared identifier",
+                None,
+                "'foo' : undeclared identifier",
+            ),
+        ];
+        for (input, severity, message) in cases {
+            assert_eq!(parse_severity(input), (*severity, *message), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_info_logs() {
+        let files = vec!["main.frag".to_string(), "camera.glsl".to_string()];
+        let cases: &[InfoLogCase] = &[
+            // Mesa
+            (
+                "0:12(5): error: `foo' undeclared",
+                Severity::Error,
+                Some("main.frag"),
+                Some(12),
+                "`foo' undeclared",
+            ),
+            (
+                "1:3(10): warning: `view' used uninitialized",
+                Severity::Warning,
+                Some("camera.glsl"),
+                Some(3),
+                "`view' used uninitialized",
+            ),
+            (
+                "error: vertex shader output `uv' is not read by the fragment shader",
+                Severity::Error,
+                None,
+                None,
+                "vertex shader output `uv' is not read by the fragment shader",
+            ),
+            // NVIDIA
+            (
+                "0(12) : error C1008: undefined variable \"foo\"",
+                Severity::Error,
+                Some("main.frag"),
+                Some(12),
+                "undefined variable \"foo\"",
+            ),
+            (
+                "1(7) : warning C7050: \"x\" might be used before being initialized",
+                Severity::Warning,
+                Some("camera.glsl"),
+                Some(7),
+                "\"x\" might be used before being initialized",
+            ),
+            // AMD and Intel
+            (
+                "ERROR: 0:12: 'foo' : undeclared identifier",
+                Severity::Error,
+                Some("main.frag"),
+                Some(12),
+                "'foo' : undeclared identifier",
+            ),
+            (
+                "WARNING: 1:4: 'view' : variable is not used",
+                Severity::Warning,
+                Some("camera.glsl"),
+                Some(4),
+                "'view' : variable is not used",
+            ),
+            (
+                "ERROR: 1 compilation errors.  No code generated.",
+                Severity::Error,
+                None,
+                None,
+                "1 compilation errors.  No code generated.",
+            ),
+            // A source string number without a file keeps the line.
+            (
+                "7:2(1): error: syntax error",
+                Severity::Error,
+                None,
+                Some(2),
+                "syntax error",
+            ),
+            ("Vertex info", Severity::Info, None, None, "Vertex info"),
+        ];
+        for (input, severity, file, line, message) in cases {
+            let messages = parse_info_log(input, &files);
+            assert_eq!(messages.len(), 1, "{}", input);
+            let parsed = &messages[0];
+            assert_eq!(parsed.severity, *severity, "{}", input);
+            assert_eq!(parsed.file.as_deref(), *file, "{}", input);
+            assert_eq!(parsed.line, *line, "{}", input);
+            assert_eq!(parsed.message, *message, "{}", input);
+        }
+    }
+
+    #[test]
+    fn info_log_skips_empty_lines() {
+        let log = "0:1(1): error: a\n\n0:2(1): warning: b\n\0";
+        let messages = parse_info_log(log, &[]);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].severity, Severity::Error);
+        assert_eq!(messages[1].severity, Severity::Warning);
+        assert_eq!(messages[1].file, None);
+    }
+}
@@ -1,136 +1,674 @@
 extern crate cgmath;
 extern crate gl;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::ffi::CString;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use cgmath::*;
 
+use crate::animation::*;
+use crate::buffers::*;
+use crate::camera::*;
+use crate::context::*;
+use crate::screenshot::*;
+use crate::shader_error::*;
+
 pub struct Shader {
     id: gl::types::GLuint,
+    uniform_locations: HashMap<String, gl::types::GLint>,
+    reported_missing_uniforms: RefCell<HashSet<String>>,
+    stages: Vec<(gl::types::GLenum, ShaderSource)>,
+    watched_files: Vec<(String, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+fn modification_time(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|m| m.modified()).ok()
+}
+
+// Shader source with all #include directives expanded. Every #line directive refers
+// to a source string number which is an index into `files`. A file is expanded only
+// at its first include, so headers shared by several includes are not duplicated.
+pub struct PreprocessedSource {
+    pub source: String,
+    pub files: Vec<String>,
+}
+
+pub fn preprocess_shader_file(filename: &str) -> Result<PreprocessedSource, String> {
+    preprocess_shader_stage(filename, Vec::new())
+}
+
+// Numbers new files after the ones in `files`, so all stages of a program share one
+// table and the source string numbers in a link log name the right file.
+pub fn preprocess_shader_stage(
+    filename: &str,
+    files: Vec<String>,
+) -> Result<PreprocessedSource, String> {
+    let mut res = PreprocessedSource {
+        source: String::new(),
+        files,
+    };
+    expand_includes(
+        Path::new(filename),
+        &mut Vec::new(),
+        &mut HashSet::new(),
+        &mut res,
+    )?;
+    Ok(res)
+}
+
+fn parse_include_directive(line: &str) -> Option<Result<&str, ()>> {
+    let rest = line.trim_start().strip_prefix("#")?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim();
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+        Some(Ok(&rest[1..rest.len() - 1]))
+    } else {
+        Some(Err(()))
+    }
+}
+
+fn expand_includes(
+    path: &Path,
+    include_stack: &mut Vec<PathBuf>,
+    expanded: &mut HashSet<PathBuf>,
+    res: &mut PreprocessedSource,
+) -> Result<(), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|_| format!("Cannot read shader source file: {}", path.display()))?;
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if include_stack.contains(&canonical) {
+        return Err(format!(
+            "Include cycle detected: {} -> {}",
+            include_stack
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+            path.display()
+        ));
+    }
+    if !expanded.insert(canonical.clone()) {
+        return Ok(());
+    }
+
+    let filename = path.to_string_lossy().into_owned();
+    let file_index = match res.files.iter().position(|f| *f == filename) {
+        Some(index) => index,
+        None => {
+            res.files.push(filename.clone());
+            res.files.len() - 1
+        }
+    };
+    let is_included = !include_stack.is_empty();
+    if is_included {
+        res.source.push_str(&format!("#line 1 {}\n", file_index));
+    }
+    include_stack.push(canonical);
+    // Source string 0 is assumed until the first #line, which only is right for the
+    // first file of the table.
+    let mut needs_line_directive = !is_included && file_index != 0;
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        if is_included && line.trim_start().starts_with("#version") {
+            return Err(format!(
+                "{}:{}: included files must not contain #version",
+                filename, line_number
+            ));
+        }
+        match parse_include_directive(line) {
+            None => {
+                res.source.push_str(line);
+                res.source.push('\n');
+                if needs_line_directive && line.trim_start().starts_with("#version") {
+                    res.source
+                        .push_str(&format!("#line {} {}\n", line_number + 1, file_index));
+                    needs_line_directive = false;
+                }
+            }
+            Some(Err(())) => {
+                return Err(format!(
+                    "{}:{}: malformed #include directive, expected #include \"file\"",
+                    filename, line_number
+                ));
+            }
+            Some(Ok(include)) => {
+                expand_includes(&directory.join(include), include_stack, expanded, res)
+                    .map_err(|msg| format!("{}:{}: {}", filename, line_number, msg))?;
+                res.source
+                    .push_str(&format!("#line {} {}\n", line_number + 1, file_index));
+            }
+        }
+    }
+
+    include_stack.pop();
+    Ok(())
+}
+
+#[derive(Clone)]
+enum ShaderSource {
+    File(String),
+    Inline(String),
+}
+
+pub struct ShaderBuilder {
+    stages: Vec<(gl::types::GLenum, ShaderSource)>,
+}
+
+impl ShaderBuilder {
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    pub fn stage(mut self, shader_type: gl::types::GLenum, filename: &str) -> Self {
+        self.stages
+            .push((shader_type, ShaderSource::File(filename.to_string())));
+        self
+    }
+
+    pub fn stage_source(mut self, shader_type: gl::types::GLenum, source: &str) -> Self {
+        self.stages
+            .push((shader_type, ShaderSource::Inline(source.to_string())));
+        self
+    }
+
+    pub fn vertex(self, filename: &str) -> Self {
+        self.stage(gl::VERTEX_SHADER, filename)
+    }
+
+    pub fn tess_control(self, filename: &str) -> Self {
+        self.stage(gl::TESS_CONTROL_SHADER, filename)
+    }
+
+    pub fn tess_evaluation(self, filename: &str) -> Self {
+        self.stage(gl::TESS_EVALUATION_SHADER, filename)
+    }
+
+    pub fn geometry(self, filename: &str) -> Self {
+        self.stage(gl::GEOMETRY_SHADER, filename)
+    }
+
+    pub fn fragment(self, filename: &str) -> Self {
+        self.stage(gl::FRAGMENT_SHADER, filename)
+    }
+
+    pub fn compute(self, filename: &str) -> Self {
+        self.stage(gl::COMPUTE_SHADER, filename)
+    }
+
+    // `supports` tells whether the context reaches a version or has an extension, see
+    // gl_supports.
+    fn validate<F>(&self, supports: F) -> Result<(), ShaderError>
+    where
+        F: Fn((i32, i32), &str) -> bool,
+    {
+        let has = |shader_type| self.stages.iter().any(|(t, _)| *t == shader_type);
+        let invalid = |msg: String| Err(ShaderError::new(ShaderErrorKind::InvalidStages, msg));
+        if self.stages.is_empty() {
+            return invalid("Shader program needs at least one stage".to_string());
+        }
+        for (i, (shader_type, _)) in self.stages.iter().enumerate() {
+            if self.stages[..i].iter().any(|(t, _)| t == shader_type) {
+                return invalid(format!(
+                    "Shader stage {} attached more than once",
+                    stage_name(*shader_type)
+                ));
+            }
+        }
+        if has(gl::COMPUTE_SHADER) {
+            if self.stages.len() > 1 {
+                return invalid("Compute shader cannot be combined with other stages".to_string());
+            }
+            if !supports((4, 3), "GL_ARB_compute_shader") {
+                return Err(ShaderError {
+                    stage: Some(gl::COMPUTE_SHADER),
+                    path: match &self.stages[0].1 {
+                        ShaderSource::File(filename) => Some(filename.clone()),
+                        ShaderSource::Inline(_) => None,
+                    },
+                    ..ShaderError::new(
+                        ShaderErrorKind::Unsupported,
+                        "Compute shaders need OpenGL 4.3 or GL_ARB_compute_shader".to_string(),
+                    )
+                });
+            }
+            return Ok(());
+        }
+        if !has(gl::VERTEX_SHADER) {
+            return invalid("Graphics program requires a vertex stage".to_string());
+        }
+        if has(gl::TESS_CONTROL_SHADER) && !has(gl::TESS_EVALUATION_SHADER) {
+            return invalid("Tessellation control stage requires an evaluation stage".to_string());
+        }
+        if has(gl::TESS_EVALUATION_SHADER) && !has(gl::TESS_CONTROL_SHADER) {
+            return invalid("Tessellation evaluation stage requires a control stage".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<Shader, ShaderError> {
+        self.validate(gl_supports)?;
+        Shader::from_stages(&self.stages)
+    }
+}
+
+// Blocks shared between all programs. Any shader declaring one of them gets it bound
+// to the block's fixed binding point when linked.
+const SHARED_UNIFORM_BLOCKS: &[UniformBlockLayout] =
+    &[CameraBlock::LAYOUT, SkinBlock::LAYOUT, OutputBlock::LAYOUT];
+
+// Needs OpenGL 4.2, which every context running compute shaders has.
+pub fn memory_barrier(barriers: gl::types::GLbitfield) {
+    assert!(
+        gl_supports((4, 2), "GL_ARB_shader_image_load_store"),
+        "memory barriers need OpenGL 4.2 or GL_ARB_shader_image_load_store"
+    );
+    unsafe {
+        gl::MemoryBarrier(barriers);
+    }
+}
+
+pub fn memory_barrier_all() {
+    memory_barrier(gl::ALL_BARRIER_BITS);
 }
 
 impl Shader {
+    const HOT_RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
     pub fn new(
         vertex_shader_filename: &str,
         fragment_shader_filename: &str,
-    ) -> Result<Self, String> {
-        let vertex_sh = Shader::compile_shader_object(vertex_shader_filename, gl::VERTEX_SHADER)?;
-        let fragment_sh =
-            Shader::compile_shader_object(fragment_shader_filename, gl::FRAGMENT_SHADER)?;
-
-        let id = Shader::link_shader_and_destroy_objects(vertex_sh, fragment_sh)?;
-        Ok(Self { id })
+    ) -> Result<Self, ShaderError> {
+        ShaderBuilder::new()
+            .vertex(vertex_shader_filename)
+            .fragment(fragment_shader_filename)
+            .build()
     }
 
     pub fn new_from_source(
         vertex_shader_src: &str,
         fragment_shader_src: &str,
-    ) -> Result<Self, String> {
-        let vertex_sh =
-            Shader::compile_shader_object_from_source(vertex_shader_src, gl::VERTEX_SHADER)?;
-        let fragment_sh =
-            Shader::compile_shader_object_from_source(fragment_shader_src, gl::FRAGMENT_SHADER)?;
+    ) -> Result<Self, ShaderError> {
+        ShaderBuilder::new()
+            .stage_source(gl::VERTEX_SHADER, vertex_shader_src)
+            .stage_source(gl::FRAGMENT_SHADER, fragment_shader_src)
+            .build()
+    }
+
+    pub fn new_compute(compute_shader_filename: &str) -> Result<Self, ShaderError> {
+        ShaderBuilder::new()
+            .compute(compute_shader_filename)
+            .build()
+    }
+
+    fn from_stages(stages: &[(gl::types::GLenum, ShaderSource)]) -> Result<Self, ShaderError> {
+        let mut objects = Vec::new();
+        let mut source_files = Vec::<String>::new();
+        for (shader_type, source) in stages {
+            let compiled = match source {
+                ShaderSource::File(filename) => {
+                    Shader::compile_shader_object(filename, *shader_type, source_files.clone())
+                }
+                ShaderSource::Inline(source) => {
+                    Shader::compile_shader_object_from_source(source, *shader_type, &[])
+                        .map(|object| (object, source_files.clone()))
+                }
+            };
+            match compiled {
+                Ok((object, files)) => {
+                    objects.push(object);
+                    source_files = files;
+                }
+                Err(error) => {
+                    for object in objects {
+                        unsafe {
+                            gl::DeleteShader(object);
+                        }
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        let id = Shader::link_shader_and_destroy_objects(&objects, &source_files)?;
+        let mut shader = Shader::from_program(id)?;
+        shader.stages = stages.to_vec();
+        shader.watched_files = source_files
+            .into_iter()
+            .map(|filename| {
+                let modified = modification_time(&filename);
+                (filename, modified)
+            })
+            .collect();
+        Ok(shader)
+    }
+
+    fn from_program(id: gl::types::GLuint) -> Result<Self, ShaderError> {
+        let shader = Self {
+            id,
+            uniform_locations: Shader::query_uniform_locations(id),
+            reported_missing_uniforms: RefCell::new(HashSet::new()),
+            stages: Vec::new(),
+            watched_files: Vec::new(),
+            last_poll: Instant::now(),
+        };
+        Shader::bind_uniform_blocks(id)
+            .map_err(|msg| ShaderError::new(ShaderErrorKind::Link, msg))?;
+        Ok(shader)
+    }
+
+    fn bind_uniform_blocks(id: gl::types::GLuint) -> Result<(), String> {
+        for layout in SHARED_UNIFORM_BLOCKS {
+            let name = CString::new(layout.name).unwrap();
+            let index = unsafe { gl::GetUniformBlockIndex(id, name.as_ptr()) };
+            if index == gl::INVALID_INDEX {
+                continue;
+            }
+            Shader::check_uniform_block_layout(id, index, layout)?;
+            unsafe {
+                gl::UniformBlockBinding(id, index, layout.binding);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_uniform_block_layout(
+        id: gl::types::GLuint,
+        index: gl::types::GLuint,
+        layout: &UniformBlockLayout,
+    ) -> Result<(), String> {
+        let mut size = 0;
+        unsafe {
+            gl::GetActiveUniformBlockiv(id, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+        }
+        if size as usize != layout.size {
+            return Err(format!(
+                "Uniform block {} is {} bytes in the shader, expected {}, is it declared with layout (std140)?",
+                layout.name, size, layout.size
+            ));
+        }
+
+        for (member, offset) in layout.members {
+            let name = CString::new(*member).unwrap();
+            let mut member_index = gl::INVALID_INDEX;
+            let mut member_offset = 0;
+            unsafe {
+                gl::GetUniformIndices(id, 1, &name.as_ptr(), &mut member_index);
+            }
+            if member_index == gl::INVALID_INDEX {
+                return Err(format!(
+                    "Uniform block {} has no member {}",
+                    layout.name, member
+                ));
+            }
+            unsafe {
+                gl::GetActiveUniformsiv(
+                    id,
+                    1,
+                    &member_index,
+                    gl::UNIFORM_OFFSET,
+                    &mut member_offset,
+                );
+            }
+            if member_offset as usize != *offset {
+                return Err(format!(
+                    "Uniform block {} member {} is at offset {}, expected {}",
+                    layout.name, member, member_offset, offset
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_compute(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|(shader_type, _)| *shader_type == gl::COMPUTE_SHADER)
+    }
+
+    pub fn dispatch(&self, groups_x: u32, groups_y: u32, groups_z: u32) {
+        assert!(self.is_compute(), "dispatch called on a non-compute shader");
+        self.bind();
+        unsafe {
+            gl::DispatchCompute(groups_x, groups_y, groups_z);
+        }
+    }
+
+    // Recompiles the program when one of its source files changed on disk. On failure
+    // the previous program stays active. Returns true when the program was replaced,
+    // in which case all uniforms need to be set again.
+    pub fn reload_if_changed(&mut self) -> bool {
+        if self.watched_files.is_empty()
+            || self.last_poll.elapsed() < Self::HOT_RELOAD_POLL_INTERVAL
+        {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self
+            .watched_files
+            .iter()
+            .any(|(filename, modified)| modification_time(filename) != *modified);
+        if !changed {
+            return false;
+        }
+        // Update the timestamps up front, so a broken file is reported once per edit.
+        for (filename, modified) in &mut self.watched_files {
+            *modified = modification_time(filename);
+        }
 
-        let id = Shader::link_shader_and_destroy_objects(vertex_sh, fragment_sh)?;
-        Ok(Self { id })
+        match Shader::from_stages(&self.stages) {
+            Ok(shader) => {
+                println!("Reloaded shader: {}", self.describe_files());
+                *self = shader;
+                true
+            }
+            Err(error) => {
+                println!("{}\nKeeping previous program", error);
+                false
+            }
+        }
+    }
+
+    fn describe_files(&self) -> String {
+        self.stages
+            .iter()
+            .filter_map(|(_, source)| match source {
+                ShaderSource::File(filename) => Some(filename.as_str()),
+                ShaderSource::Inline(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn query_uniform_locations(id: gl::types::GLuint) -> HashMap<String, gl::types::GLint> {
+        let mut count = 0;
+        let mut max_name_len = 0;
+        unsafe {
+            gl::GetProgramiv(id, gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_name_len);
+        }
+
+        let get_location = |name: &str| {
+            let n = CString::new(name).unwrap();
+            unsafe { gl::GetUniformLocation(id, n.as_ptr()) }
+        };
+
+        let mut locations = HashMap::new();
+        for index in 0..count as gl::types::GLuint {
+            let mut name = vec![0u8; max_name_len as usize];
+            let mut name_len = 0;
+            let mut array_size = 0;
+            let mut uniform_type = 0;
+            unsafe {
+                gl::GetActiveUniform(
+                    id,
+                    index,
+                    max_name_len,
+                    &mut name_len,
+                    &mut array_size,
+                    &mut uniform_type,
+                    name.as_mut_ptr() as *mut gl::types::GLchar,
+                );
+            }
+            name.truncate(name_len as usize);
+            let name = String::from_utf8_lossy(&name).into_owned();
+
+            let location = get_location(&name);
+            // Members of uniform blocks have no location.
+            if location == -1 {
+                continue;
+            }
+
+            // Arrays are reported once as "name[0]", but are also addressed as "name"
+            // and element by element.
+            if name.ends_with("[0]") {
+                let base = &name[..name.len() - 3];
+                locations.insert(base.to_string(), location);
+                for element in 1..array_size {
+                    let element_name = format!("{}[{}]", base, element);
+                    let element_location = get_location(&element_name);
+                    locations.insert(element_name, element_location);
+                }
+            }
+            locations.insert(name, location);
+        }
+        locations
     }
 
     fn link_shader_and_destroy_objects(
-        vertex_shader: gl::types::GLuint,
-        fragment_shader: gl::types::GLuint,
-    ) -> Result<gl::types::GLuint, String> {
+        objects: &[gl::types::GLuint],
+        files: &[String],
+    ) -> Result<gl::types::GLuint, ShaderError> {
         let id = unsafe { gl::CreateProgram() };
+        let mut status = 0;
         unsafe {
-            gl::AttachShader(id, vertex_shader);
-            gl::AttachShader(id, fragment_shader);
+            for object in objects {
+                gl::AttachShader(id, *object);
+            }
             gl::LinkProgram(id);
-            gl::DetachShader(id, vertex_shader);
-            gl::DetachShader(id, fragment_shader);
-            gl::DeleteShader(vertex_shader);
-            gl::DeleteShader(fragment_shader);
+            for object in objects {
+                gl::DetachShader(id, *object);
+                gl::DeleteShader(*object);
+            }
+            gl::GetProgramiv(id, gl::LINK_STATUS, &mut status);
         }
-        let failure = Shader::if_and_print_error_msg_from_program(id);
-        if failure {
-            return Err(format!(
-                "Failed to link shader: {} from vert {} and frag {}",
-                id, vertex_shader, fragment_shader
-            ));
+        let messages = parse_info_log(&Shader::program_info_log(id), files);
+        if status == gl::FALSE as gl::types::GLint {
+            unsafe {
+                gl::DeleteProgram(id);
+            }
+            return Err(ShaderError {
+                kind: ShaderErrorKind::Link,
+                stage: None,
+                path: None,
+                messages,
+            });
         }
+        print_warnings(&messages);
         Ok(id)
     }
 
+    // Returns the shader object together with `files` extended by every file it was
+    // assembled from.
     fn compile_shader_object(
         filename: &str,
         shader_type: gl::types::GLenum,
-    ) -> Result<gl::types::GLuint, String> {
-        let source = fs::read_to_string(filename)
-            .map_err(|_| format!("Cannot read shader source file: {}", filename))?;
-        Shader::compile_shader_object_from_source(&source, shader_type)
+        files: Vec<String>,
+    ) -> Result<(gl::types::GLuint, Vec<String>), ShaderError> {
+        let preprocessed = preprocess_shader_stage(filename, files).map_err(|msg| ShaderError {
+            stage: Some(shader_type),
+            path: Some(filename.to_string()),
+            ..ShaderError::new(ShaderErrorKind::Source, msg)
+        })?;
+        let id = Shader::compile_shader_object_from_source(
+            &preprocessed.source,
+            shader_type,
+            &preprocessed.files,
+        )
+        .map_err(|error| ShaderError {
+            path: Some(filename.to_string()),
+            ..error
+        })?;
+        Ok((id, preprocessed.files))
     }
 
+    // `files` maps the source string numbers used in the info log back to file names.
     fn compile_shader_object_from_source(
         source: &str,
         shader_type: gl::types::GLenum,
-    ) -> Result<gl::types::GLuint, String> {
+        files: &[String],
+    ) -> Result<gl::types::GLuint, ShaderError> {
         let strptr: *const gl::types::GLchar = source.as_ptr() as *const i8;
         let strlen: gl::types::GLint = source.len().try_into().unwrap();
         let id;
+        let mut status = 0;
         unsafe {
             id = gl::CreateShader(shader_type);
             gl::ShaderSource(id, 1, &strptr, &strlen);
             gl::CompileShader(id);
+            gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut status);
         }
-        let failure = Shader::if_and_print_error_msg_from_objects(id);
-        if failure {
-            return Err(format!("Failed to compile shader: {}", id));
+        let messages = parse_info_log(&Shader::shader_info_log(id), files);
+        if status == gl::FALSE as gl::types::GLint {
+            unsafe {
+                gl::DeleteShader(id);
+            }
+            return Err(ShaderError {
+                kind: ShaderErrorKind::Compile,
+                stage: Some(shader_type),
+                path: None,
+                messages,
+            });
         }
+        print_warnings(&messages);
         Ok(id)
     }
 
-    fn if_and_print_error_msg_from_objects(id: gl::types::GLuint) -> bool {
+    fn shader_info_log(id: gl::types::GLuint) -> String {
         let mut infolog_len: gl::types::GLint = 0;
         unsafe {
             gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut infolog_len);
         }
-
-        if infolog_len > 0 {
-            let msg = create_cstring_whitespace(infolog_len as usize);
-            unsafe {
-                gl::GetShaderInfoLog(
-                    id,
-                    infolog_len,
-                    std::ptr::null_mut(),
-                    msg.as_ptr() as *mut i8,
-                );
-            }
-            println!("{}", msg.to_string_lossy().to_owned());
-            return true;
+        if infolog_len <= 0 {
+            return String::new();
         }
-        false
+        let msg = create_cstring_whitespace(infolog_len as usize);
+        unsafe {
+            gl::GetShaderInfoLog(
+                id,
+                infolog_len,
+                std::ptr::null_mut(),
+                msg.as_ptr() as *mut i8,
+            );
+        }
+        msg.to_string_lossy().into_owned()
     }
 
-    fn if_and_print_error_msg_from_program(id: gl::types::GLuint) -> bool {
+    fn program_info_log(id: gl::types::GLuint) -> String {
         let mut infolog_len: gl::types::GLint = 0;
         unsafe {
             gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut infolog_len);
         }
-
-        if infolog_len > 0 {
-            let msg = create_cstring_whitespace(infolog_len as usize);
-            unsafe {
-                gl::GetProgramInfoLog(
-                    id,
-                    infolog_len,
-                    std::ptr::null_mut(),
-                    msg.as_ptr() as *mut i8,
-                );
-            }
-            println!("{}", msg.to_string_lossy().to_owned());
-            return true;
+        if infolog_len <= 0 {
+            return String::new();
         }
-        false
+        let msg = create_cstring_whitespace(infolog_len as usize);
+        unsafe {
+            gl::GetProgramInfoLog(
+                id,
+                infolog_len,
+                std::ptr::null_mut(),
+                msg.as_ptr() as *mut i8,
+            );
+        }
+        msg.to_string_lossy().into_owned()
     }
 
     pub fn bind(&self) {
@@ -139,55 +677,73 @@ impl Shader {
         }
     }
 
+    // Unknown names resolve to -1, which GL silently ignores. They are usually uniforms
+    // optimized out by the driver, so they are only reported the first time.
     fn get_uniform_location(&self, name: &str) -> i32 {
-        self.bind();
-        let n = CString::new(name).unwrap();
-        let location = unsafe { gl::GetUniformLocation(self.id, n.as_ptr()) };
-        if location == -1 {
-            println!("failed to locate uniform: {}", name);
+        match self.uniform_locations.get(name) {
+            Some(location) => *location,
+            None => {
+                if self
+                    .reported_missing_uniforms
+                    .borrow_mut()
+                    .insert(name.to_string())
+                {
+                    println!(
+                        "warning: uniform {} is not active in shader {}",
+                        name, self.id
+                    );
+                }
+                -1
+            }
         }
-        location
     }
 
     pub fn set_uniform_1f(&self, name: &str, val: &f32) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::Uniform1f(id, *val);
+            gl::ProgramUniform1f(self.id, location, *val);
+        }
+    }
+
+    pub fn set_uniform_1fv(&self, name: &str, val: &[f32]) {
+        let location = self.get_uniform_location(name);
+        unsafe {
+            gl::ProgramUniform1fv(self.id, location, val.len() as i32, val.as_ptr());
         }
     }
 
     pub fn set_uniform_3f(&self, name: &str, val: &Vector3<f32>) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::Uniform3fv(id, 1, val.as_ptr());
+            gl::ProgramUniform3fv(self.id, location, 1, val.as_ptr());
         }
     }
 
     pub fn set_uniform_4f(&self, name: &str, val: &Vector4<f32>) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::Uniform4fv(id, 1, val.as_ptr());
+            gl::ProgramUniform4fv(self.id, location, 1, val.as_ptr());
         }
     }
 
     pub fn set_uniform_mat3f(&self, name: &str, val: &Matrix3<f32>) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::UniformMatrix3fv(id, 1, gl::FALSE, val.as_ptr());
+            gl::ProgramUniformMatrix3fv(self.id, location, 1, gl::FALSE, val.as_ptr());
         }
     }
 
     pub fn set_uniform_mat4f(&self, name: &str, val: &Matrix4<f32>) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::UniformMatrix4fv(id, 1, gl::FALSE, val.as_ptr());
+            gl::ProgramUniformMatrix4fv(self.id, location, 1, gl::FALSE, val.as_ptr());
         }
     }
 
     pub fn set_uniform_1i(&self, name: &str, val: &i32) {
-        let id = self.get_uniform_location(name);
+        let location = self.get_uniform_location(name);
         unsafe {
-            gl::Uniform1i(id, *val);
+            gl::ProgramUniform1i(self.id, location, *val);
         }
     }
 
@@ -207,8 +763,263 @@ impl Drop for Shader {
     }
 }
 
+fn print_warnings(messages: &[ShaderMessage]) {
+    for message in messages {
+        if message.severity != Severity::Info {
+            println!("{}", message);
+        }
+    }
+}
+
 fn create_cstring_whitespace(len: usize) -> CString {
     let mut buffer = Vec::<u8>::with_capacity(len + 1);
     buffer.extend([b' '].iter().cycle().take(len));
     unsafe { CString::from_vec_unchecked(buffer) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `files` into a fresh directory and returns the path of the first one.
+    fn write_sources(test_name: &str, files: &[(&str, &str)]) -> String {
+        let directory = std::env::temp_dir().join(format!(
+            "shader_includes_{}_{}",
+            test_name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, source) in files {
+            fs::write(directory.join(name), source).unwrap();
+        }
+        directory.join(files[0].0).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parse_include_directives() {
+        assert_eq!(
+            parse_include_directive("#include \"a.glsl\""),
+            Some(Ok("a.glsl"))
+        );
+        assert_eq!(
+            parse_include_directive("  #  include   \"b.glsl\"  "),
+            Some(Ok("b.glsl"))
+        );
+        assert_eq!(parse_include_directive("#include <a.glsl>"), Some(Err(())));
+        assert_eq!(parse_include_directive("#include"), Some(Err(())));
+        assert_eq!(parse_include_directive("#version 450"), None);
+        assert_eq!(parse_include_directive("float include = 1.0;"), None);
+    }
+
+    #[test]
+    fn includes_are_expanded_with_line_directives() {
+        let main = write_sources(
+            "nested",
+            &[
+                (
+                    "main.frag",
+                    "#version 450\n#include \"a.glsl\"\nvoid main() {}\n",
+                ),
+                ("a.glsl", "float a;\n#include \"b.glsl\"\nfloat c;\n"),
+                ("b.glsl", "float b;\n"),
+            ],
+        );
+        let res = preprocess_shader_file(&main).unwrap();
+        assert_eq!(res.files.len(), 3);
+        assert!(res.files[1].ends_with("a.glsl"));
+        assert!(res.files[2].ends_with("b.glsl"));
+        assert_eq!(
+            res.source,
+            "#version 450\n\
+             #line 1 1\nfloat a;\n\
+             #line 1 2\nfloat b;\n\
+             #line 3 1\nfloat c;\n\
+             #line 3 0\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn diamond_includes_expand_shared_file_once() {
+        let main = write_sources(
+            "diamond",
+            &[
+                (
+                    "main.frag",
+                    "#include \"left.glsl\"\n#include \"right.glsl\"\n",
+                ),
+                ("left.glsl", "#include \"common.glsl\"\nfloat left;\n"),
+                ("right.glsl", "#include \"common.glsl\"\nfloat right;\n"),
+                ("common.glsl", "float common_value;\n"),
+            ],
+        );
+        let res = preprocess_shader_file(&main).unwrap();
+        assert_eq!(res.source.matches("float common_value;").count(), 1);
+        assert!(res.source.contains("float left;"));
+        assert!(res.source.contains("float right;"));
+        assert_eq!(res.files.len(), 4);
+    }
+
+    #[test]
+    fn stages_share_one_file_table() {
+        let vertex = write_sources(
+            "stages",
+            &[
+                ("main.vert", "#version 450\n#include \"camera.glsl\"\n"),
+                (
+                    "main.frag",
+                    "#version 450\n#include \"camera.glsl\"\nvoid main() {}\n",
+                ),
+                ("camera.glsl", "float camera;\n"),
+            ],
+        );
+        let vertex = preprocess_shader_stage(&vertex, Vec::new()).unwrap();
+        assert_eq!(vertex.files.len(), 2);
+
+        let fragment = vertex.files[0].replace("main.vert", "main.frag");
+        let fragment = preprocess_shader_stage(&fragment, vertex.files.clone()).unwrap();
+        assert_eq!(fragment.files.len(), 3);
+        assert_eq!(fragment.files[..2], vertex.files[..]);
+        assert!(fragment.files[2].ends_with("main.frag"));
+        assert_eq!(
+            fragment.source,
+            "#version 450\n#line 2 2\n\
+             #line 1 1\nfloat camera;\n\
+             #line 3 2\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let main = write_sources(
+            "cycle",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"a.glsl\"\n"),
+            ],
+        );
+        let msg = preprocess_shader_file(&main).err().unwrap();
+        assert!(msg.contains("Include cycle detected"), "{}", msg);
+        assert!(msg.contains("a.glsl -> "), "{}", msg);
+    }
+
+    #[test]
+    fn self_include_is_rejected() {
+        let main = write_sources("self", &[("main.frag", "#include \"main.frag\"\n")]);
+        let msg = preprocess_shader_file(&main).err().unwrap();
+        assert!(msg.contains("Include cycle detected"), "{}", msg);
+    }
+
+    #[test]
+    fn malformed_includes_are_rejected() {
+        let main = write_sources(
+            "malformed",
+            &[
+                ("main.frag", "#include \"version.glsl\"\n"),
+                ("version.glsl", "float a;\n#version 450\n"),
+                ("bracket.frag", "\n#include <a.glsl>\n"),
+            ],
+        );
+        let msg = preprocess_shader_file(&main).err().unwrap();
+        assert!(msg.contains("version.glsl:2: included files must not contain #version"));
+
+        let bracket = main.replace("main.frag", "bracket.frag");
+        let msg = preprocess_shader_file(&bracket).err().unwrap();
+        assert!(msg.contains("bracket.frag:2: malformed #include directive"));
+
+        let missing = main.replace("main.frag", "missing.frag");
+        assert!(preprocess_shader_file(&missing).is_err());
+    }
+
+    fn validate_stages(stages: &[gl::types::GLenum], version: (i32, i32)) -> Result<(), String> {
+        stages
+            .iter()
+            .fold(ShaderBuilder::new(), |builder, stage| {
+                builder.stage(*stage, "test.glsl")
+            })
+            .validate(|required, _| version >= required)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn valid_stage_combinations() {
+        let cases: &[&[gl::types::GLenum]] = &[
+            &[gl::VERTEX_SHADER, gl::FRAGMENT_SHADER],
+            &[gl::VERTEX_SHADER],
+            &[gl::VERTEX_SHADER, gl::GEOMETRY_SHADER, gl::FRAGMENT_SHADER],
+            &[
+                gl::VERTEX_SHADER,
+                gl::TESS_CONTROL_SHADER,
+                gl::TESS_EVALUATION_SHADER,
+                gl::FRAGMENT_SHADER,
+            ],
+        ];
+        for stages in cases {
+            assert_eq!(validate_stages(stages, (4, 1)), Ok(()), "{:?}", stages);
+        }
+        assert_eq!(validate_stages(&[gl::COMPUTE_SHADER], (4, 3)), Ok(()));
+    }
+
+    #[test]
+    fn invalid_stage_combinations_are_rejected() {
+        let cases: &[(&[gl::types::GLenum], &str)] = &[
+            (&[], "needs at least one stage"),
+            (
+                &[gl::VERTEX_SHADER, gl::FRAGMENT_SHADER, gl::VERTEX_SHADER],
+                "vertex attached more than once",
+            ),
+            (
+                &[gl::COMPUTE_SHADER, gl::VERTEX_SHADER, gl::FRAGMENT_SHADER],
+                "cannot be combined with other stages",
+            ),
+            (
+                &[gl::VERTEX_SHADER, gl::COMPUTE_SHADER],
+                "cannot be combined with other stages",
+            ),
+            (&[gl::FRAGMENT_SHADER], "requires a vertex stage"),
+            (
+                &[gl::GEOMETRY_SHADER, gl::FRAGMENT_SHADER],
+                "requires a vertex stage",
+            ),
+            (
+                &[
+                    gl::VERTEX_SHADER,
+                    gl::TESS_CONTROL_SHADER,
+                    gl::FRAGMENT_SHADER,
+                ],
+                "control stage requires an evaluation stage",
+            ),
+            (
+                &[
+                    gl::VERTEX_SHADER,
+                    gl::TESS_EVALUATION_SHADER,
+                    gl::FRAGMENT_SHADER,
+                ],
+                "evaluation stage requires a control stage",
+            ),
+        ];
+        for (stages, expected) in cases {
+            let msg = validate_stages(stages, (4, 6)).err().unwrap();
+            assert!(
+                msg.starts_with("Invalid stages for shader program"),
+                "{}",
+                msg
+            );
+            assert!(msg.contains(expected), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn compute_needs_opengl_4_3() {
+        let msg = validate_stages(&[gl::COMPUTE_SHADER], (4, 1))
+            .err()
+            .unwrap();
+        assert!(
+            msg.starts_with("Unsupported compute shader test.glsl"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("need OpenGL 4.3"), "{}", msg);
+        assert_eq!(validate_stages(&[gl::COMPUTE_SHADER], (4, 2)), Err(msg));
+    }
+}
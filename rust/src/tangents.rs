@@ -0,0 +1,96 @@
+extern crate mikktspace;
+
+struct TangentGeometry<'a> {
+    positions: &'a [[f32; 3]],
+    normals: &'a [[f32; 3]],
+    uvs: &'a [[f32; 2]],
+    triangles: &'a [[u32; 3]],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl<'a> TangentGeometry<'a> {
+    fn vertex(&self, face: usize, vert: usize) -> usize {
+        self.triangles[face][vert] as usize
+    }
+}
+
+impl<'a> mikktspace::Geometry for TangentGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.vertex(face, vert)]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.vertex(face, vert)]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.uvs[self.vertex(face, vert)]
+    }
+
+    // MikkTSpace works on face corners. Corners sharing a vertex only disagree where it
+    // would have split the vertex, which inside a single UV chart means mirrored UVs, so
+    // the last corner simply wins.
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let vertex = self.vertex(face, vert);
+        self.tangents[vertex] = tangent;
+    }
+}
+
+// MikkTSpace tangents, one per vertex. The w component holds the handedness, so the
+// bitangent is cross(normal, tangent.xyz) * tangent.w.
+pub fn generate_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    triangles: &[[u32; 3]],
+) -> Vec<[f32; 4]> {
+    let mut geometry = TangentGeometry {
+        positions,
+        normals,
+        uvs,
+        triangles,
+        tangents: vec![[1.0, 0.0, 0.0, 1.0]; positions.len()],
+    };
+    if !mikktspace::generate_tangents(&mut geometry) {
+        println!("warning: failed to generate tangents");
+    }
+    geometry.tangents
+}
+
+pub fn triangle_list(indices: &[u32]) -> Vec<[u32; 3]> {
+    indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect()
+}
+
+// Every other triangle of a strip has its winding flipped back, degenerate triangles
+// used to join strips are dropped.
+pub fn triangle_strip(indices: &[u32]) -> Vec<[u32; 3]> {
+    indices
+        .windows(3)
+        .enumerate()
+        .filter(|(_, t)| t[0] != t[1] && t[1] != t[2] && t[0] != t[2])
+        .map(|(i, t)| {
+            if i % 2 == 0 {
+                [t[0], t[1], t[2]]
+            } else {
+                [t[1], t[0], t[2]]
+            }
+        })
+        .collect()
+}
+
+pub fn triangle_fan(indices: &[u32]) -> Vec<[u32; 3]> {
+    (1..indices.len().saturating_sub(1))
+        .map(|i| [indices[0], indices[i], indices[i + 1]])
+        .collect()
+}
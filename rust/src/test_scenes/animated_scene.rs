@@ -0,0 +1,321 @@
+extern crate cgmath;
+extern crate gl;
+
+use cgmath::*;
+
+use super::*;
+use crate::buffers::*;
+use crate::camera::*;
+use crate::frustum::*;
+use crate::gltf_loader::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::scene_graph::*;
+use crate::shaders::*;
+use crate::textures::*;
+use crate::utils::*;
+
+use std::cell::Cell;
+
+pub struct AnimatedModel {
+    skybox: (Mesh, Shader, TextureCubeMap),
+    model: (SceneGraph, Shader),
+    ibl_setup: (TextureCubeMap, TextureCubeMap, Texture2D),
+    cam: Camera,
+    camera_block: UniformBuffer<CameraBlock>,
+    animation: usize,
+    animation_time: f32,
+    playing: bool,
+    moving_up: bool,
+    moving_down: bool,
+    moving_right: bool,
+    moving_left: bool,
+    framebuffer_size: (u32, u32),
+    cull_stats: Cell<CullStats>,
+}
+
+impl AnimatedModel {
+    const ENV_MAP_FACE_RESOLUTION: i32 = 1024;
+    const LUT_TEXTURE_RESOLUTION: i32 = 512;
+
+    const CAM_SPEED: f32 = 0.00003;
+    const FOV_SPEED: f32 = 1.05;
+    const MOUSE_SPEED: f32 = 0.002;
+    const SCRUB_STEP: f32 = 0.1;
+
+    fn setup_model_shader(shader: &Shader) {
+        shader.set_uniform_1i("irradiance_map", &0);
+        shader.set_uniform_1i("prefiltered_map", &1);
+        shader.set_uniform_1i("brdf_lut", &2);
+        Material::setup_shader(shader);
+    }
+
+    fn animation_duration(&self) -> f32 {
+        self.model
+            .0
+            .animations
+            .get(self.animation)
+            .map_or(0.0, |animation| animation.duration())
+    }
+
+    // Scrubbing pauses playback so the selected pose stays on screen.
+    fn scrub(&mut self, delta: f32) {
+        self.playing = false;
+        self.animation_time = (self.animation_time + delta)
+            .max(0.0)
+            .min(self.animation_duration());
+        println!("Paused at {:.2}s", self.animation_time);
+    }
+
+    fn select_next_animation(&mut self) {
+        let count = self.model.0.animations.len();
+        if count == 0 {
+            return;
+        }
+        self.animation = (self.animation + 1) % count;
+        self.animation_time = 0.0;
+        let animation = &self.model.0.animations[self.animation];
+        println!(
+            "Playing animation {}: {}",
+            self.animation,
+            animation.name.as_deref().unwrap_or("unnamed")
+        );
+    }
+}
+
+impl TestScene for AnimatedModel {
+    fn new(framebuffer_size: (u32, u32)) -> Result<Box<dyn TestScene>, String> {
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::DepthFunc(gl::LESS);
+
+            gl::Enable(gl::CULL_FACE);
+
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+
+        // Shared by the passes that render into the faces of a cube map.
+        let cube = crate_cube_buffers();
+        let skybox_texture =
+            TextureCubeMap::new_from_hdr(ENVIRONMENT_MAP, Self::ENV_MAP_FACE_RESOLUTION, &cube)?;
+
+        let mut res = Box::new(Self {
+            ibl_setup: {
+                let irr = compute_irradiance_map(&skybox_texture, &cube)?;
+                let pref = compute_prefiltered_env_map(
+                    &skybox_texture,
+                    Self::ENV_MAP_FACE_RESOLUTION,
+                    &cube,
+                )?;
+
+                let lut = compute_lut_texture(Self::LUT_TEXTURE_RESOLUTION)?;
+                (irr, pref, lut)
+            },
+            skybox: {
+                let mesh = create_skybox_buffers();
+                let shader = Shader::new("../shaders/skybox.vert", "../shaders/skybox.frag")?;
+
+                (mesh, shader, skybox_texture)
+            },
+            model: {
+                let mut graph = SceneGraph::new();
+                graph.import_gltf(
+                    GltfScene::load("../resources/skinned_tube/skinned_tube.gltf")?,
+                    None,
+                );
+                graph.update_world_transforms();
+                let shader = Shader::new(
+                    "../shaders/sphere_pbr_skinned.vert",
+                    "../shaders/gltf_pbr_ibl.frag",
+                )?;
+                Self::setup_model_shader(&shader);
+
+                (graph, shader)
+            },
+            cam: Camera::new_default(0.0, 0.0),
+            camera_block: UniformBuffer::new(),
+            animation: 0,
+            animation_time: 0.0,
+            playing: true,
+            moving_up: false,
+            moving_down: false,
+            moving_right: false,
+            moving_left: false,
+            framebuffer_size,
+            cull_stats: Cell::new(CullStats::default()),
+        });
+        unsafe {
+            gl::Viewport(0, 0, framebuffer_size.0 as i32, framebuffer_size.1 as i32);
+        }
+        res.reset();
+        Ok(res)
+    }
+
+    fn reset(&mut self) {
+        self.cam = Camera::new_default(
+            self.framebuffer_size.0 as f32,
+            self.framebuffer_size.1 as f32,
+        );
+        self.cam.position.z = 5.0;
+        self.animation_time = 0.0;
+        self.playing = true;
+    }
+
+    fn handle_event(&mut self, event: &Event<()>, _: &mut ControlFlow) {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => match input.virtual_keycode {
+                Some(VirtualKeyCode::Up) => {
+                    self.moving_up = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Down) => {
+                    self.moving_down = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Right) => {
+                    self.moving_right = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Left) => {
+                    self.moving_left = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::W) => {
+                    self.cam.perspective.fovy /= Self::FOV_SPEED;
+                    if self.cam.perspective.fovy < Rad::from(Deg(15.0)) {
+                        self.cam.perspective.fovy = Rad::from(Deg(15.0));
+                    }
+                }
+                Some(VirtualKeyCode::S) => {
+                    self.cam.perspective.fovy *= Self::FOV_SPEED;
+                    if self.cam.perspective.fovy > Rad::from(Deg(100.0)) {
+                        self.cam.perspective.fovy = Rad::from(Deg(100.0));
+                    }
+                }
+                Some(VirtualKeyCode::Space) if input.state == ElementState::Pressed => {
+                    self.playing = !self.playing;
+                }
+                Some(VirtualKeyCode::Comma) if input.state == ElementState::Pressed => {
+                    self.scrub(-Self::SCRUB_STEP);
+                }
+                Some(VirtualKeyCode::Period) if input.state == ElementState::Pressed => {
+                    self.scrub(Self::SCRUB_STEP);
+                }
+                Some(VirtualKeyCode::Home) if input.state == ElementState::Pressed => {
+                    self.animation_time = 0.0;
+                }
+                Some(VirtualKeyCode::Tab) if input.state == ElementState::Pressed => {
+                    self.select_next_animation();
+                }
+                _ => (),
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (x, y) },
+                ..
+            } => {
+                self.cam.horizontal_angle -= Rad(*x as f32 * Self::MOUSE_SPEED);
+                self.cam.vertical_angle -= Rad(*y as f32 * Self::MOUSE_SPEED);
+            }
+            _ => (),
+        }
+    }
+
+    fn update(&mut self, delta: Duration) {
+        if self.model.1.reload_if_changed() {
+            Self::setup_model_shader(&self.model.1);
+        }
+        self.skybox.1.reload_if_changed();
+
+        if self.animation < self.model.0.animations.len() {
+            if self.playing {
+                let duration = self.animation_duration();
+                self.animation_time += delta.as_secs_f32();
+                if duration > 0.0 {
+                    self.animation_time %= duration;
+                }
+            }
+            self.model.0.animate(self.animation, self.animation_time);
+        }
+        self.model.0.update_world_transforms();
+
+        let mut vel = vec3(0.0, 0.0, 0.0);
+        if self.moving_up {
+            vel += self.cam.direction();
+        }
+        if self.moving_down {
+            vel -= self.cam.direction();
+        }
+        if self.moving_right {
+            vel += self.cam.right();
+        }
+        if self.moving_left {
+            vel -= self.cam.right();
+        }
+        self.cam.position += vel * delta.as_micros() as f32 * Self::CAM_SPEED;
+    }
+
+    fn render(&self) {
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        self.camera_block.update(&self.cam.to_block());
+        self.camera_block.bind();
+        let model_shader = &self.model.1;
+        model_shader.bind();
+        self.ibl_setup.0.set_slot(&0);
+        self.ibl_setup.1.set_slot(&1);
+        self.ibl_setup.2.set_slot(&2);
+
+        let stats = self.model.0.draw(model_shader, &self.cam.frustum());
+        self.cull_stats.set(stats);
+
+        let skybox_shader = &self.skybox.1;
+        skybox_shader.bind();
+        skybox_shader.set_texture_slot("skybox", &0);
+        self.skybox.2.set_slot(&0);
+
+        draw_skybox(&self.skybox.0);
+    }
+
+    fn cull_stats(&self) -> CullStats {
+        self.cull_stats.get()
+    }
+
+    fn set_framebuffer_size(&mut self, size: (u32, u32)) {
+        self.framebuffer_size = size;
+
+        unsafe {
+            gl::Viewport(
+                0,
+                0,
+                self.framebuffer_size.0 as i32,
+                self.framebuffer_size.1 as i32,
+            );
+        }
+    }
+}
+
+impl Drop for AnimatedModel {
+    fn drop(&mut self) {
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::BLEND);
+            gl::Disable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+    }
+}
@@ -7,6 +7,8 @@ pub mod pbr_textured_spheres;
 pub use pbr_textured_spheres::*;
 pub mod glock_scene;
 pub use glock_scene::*;
+pub mod animated_scene;
+pub use animated_scene::*;
 
 use std::collections::HashMap;
 use std::time::Duration;
@@ -14,19 +16,36 @@ use std::time::Duration;
 use glutin::event::*;
 use glutin::event_loop::*;
 
+use crate::buffers::*;
+use crate::frustum::*;
+use crate::headless::*;
+use crate::screenshot::*;
+
+// Environment of the image based lighting scenes.
+pub const ENVIRONMENT_MAP: &str = "../resources/newport_loft.hdr";
+
+type SceneConstructor = fn((u32, u32)) -> Result<Box<dyn TestScene>, String>;
+
 pub struct TestApp {
     current_test: Option<Box<dyn TestScene>>,
-    scenes_map: HashMap<VirtualKeyCode, (String, fn((u32, u32)) -> Box<dyn TestScene>)>,
+    current_name: Option<String>,
+    scenes_map: HashMap<VirtualKeyCode, (String, SceneConstructor)>,
     framebuffer_size: (u32, u32),
+    pending_screenshot: Option<ScreenshotFormat>,
+    output_block: UniformBuffer<OutputBlock>,
 }
 
 impl TestApp {
     pub fn new(framebuffer_size: (u32, u32)) -> Self {
         let mut res = Self {
             current_test: None,
+            current_name: None,
             scenes_map: HashMap::new(),
             framebuffer_size: (0, 0),
+            pending_screenshot: None,
+            output_block: UniformBuffer::new(),
         };
+        res.output_block.update(&OutputBlock::new(false));
         res.reset();
         res.set_framebuffer_size(framebuffer_size);
         res
@@ -51,6 +70,81 @@ impl TestApp {
         }
     }
 
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        let fun = self
+            .scenes_map
+            .values()
+            .find(|(scene_name, _)| scene_name == name)
+            .map(|(_, fun)| *fun)
+            .ok_or(format!("No test scene named: {}", name))?;
+        let scene = fun(self.framebuffer_size)
+            .map_err(|msg| format!("Cannot load test scene {}: {}", name, msg))?;
+        self.current_test = Some(scene);
+        self.current_name = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn deselect(&mut self) {
+        self.current_test = None;
+        self.current_name = None;
+        self.reset();
+    }
+
+    // The capture happens at the end of the next render, before buffers are swapped.
+    pub fn request_screenshot(&mut self, format: ScreenshotFormat) {
+        self.pending_screenshot = Some(format);
+    }
+
+    // Float formats render the scene again into a float target with tone mapping
+    // disabled, the default framebuffer only holds tone mapped 8 bit colors.
+    fn take_screenshot(&self, format: ScreenshotFormat) {
+        let name = self.current_name.as_deref().unwrap_or("empty");
+        let result = if format.is_float() {
+            self.render_linear().and_then(|target| {
+                save_screenshot(target.id(), self.framebuffer_size, name, format)
+            })
+        } else {
+            save_screenshot(
+                current_draw_framebuffer(),
+                self.framebuffer_size,
+                name,
+                format,
+            )
+        };
+        match result {
+            Ok(path) => println!("Screenshot saved: {}", path.display()),
+            Err(msg) => println!("Screenshot failed: {}", msg),
+        }
+    }
+
+    fn render_linear(&self) -> Result<OffscreenTarget, String> {
+        let previous = current_draw_framebuffer();
+        let target = OffscreenTarget::new_with_format(self.framebuffer_size, gl::RGBA32F)?;
+        target.bind();
+        self.output_block.update(&OutputBlock::new(true));
+        match &self.current_test {
+            Some(test) => test.render(),
+            None => unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            },
+        }
+        self.output_block.update(&OutputBlock::new(false));
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous);
+        }
+        Ok(target)
+    }
+
+    pub fn scene_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .scenes_map
+            .values()
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
     fn print_map(&self) {
         println!("Available test scenes:");
         for (key, (name, _)) in &self.scenes_map {
@@ -68,67 +162,90 @@ impl TestApp {
     }
 
     pub fn handle_event(&mut self, event: &Event<()>, control_flow: &mut ControlFlow) {
-        match event {
-            Event::WindowEvent { ref event, .. } => match event {
-                WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
-                    Some(VirtualKeyCode::Back) => match &mut self.current_test {
-                        None => (),
-                        Some(_) => {
-                            self.current_test = None;
-                            self.reset();
-                        }
-                    },
-                    Some(VirtualKeyCode::Escape) => *control_flow = ControlFlow::Exit,
-                    Some(VirtualKeyCode::R) => {
-                        self.reset();
-                        if self.current_test.is_none() {
-                            self.print_map();
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { input, .. },
+            ..
+        } = event
+        {
+            match input.virtual_keycode {
+                Some(VirtualKeyCode::Back) => match &mut self.current_test {
+                    None => (),
+                    Some(_) => self.deselect(),
+                },
+                Some(VirtualKeyCode::Escape) => *control_flow = ControlFlow::Exit,
+                Some(VirtualKeyCode::F12) if input.state == ElementState::Pressed => {
+                    self.request_screenshot(ScreenshotFormat::Png);
+                }
+                Some(VirtualKeyCode::F11) if input.state == ElementState::Pressed => {
+                    self.request_screenshot(ScreenshotFormat::Hdr);
+                }
+                Some(VirtualKeyCode::F10) if input.state == ElementState::Pressed => {
+                    self.request_screenshot(ScreenshotFormat::Exr);
+                }
+                Some(VirtualKeyCode::R) => {
+                    self.reset();
+                    if self.current_test.is_none() {
+                        self.print_map();
+                    }
+                }
+                Some(key) => {
+                    if let (Some((name, fun)), None) =
+                        (self.scenes_map.get(&key), &self.current_test)
+                    {
+                        match fun(self.framebuffer_size) {
+                            Ok(scene) => {
+                                self.current_test = Some(scene);
+                                self.current_name = Some(name.clone());
+                            }
+                            Err(msg) => println!("Cannot load test scene {}: {}", name, msg),
                         }
                     }
-                    Some(key) => match self.scenes_map.get(&key) {
-                        Some((_, fun)) => match self.current_test {
-                            Some(_) => (),
-                            None => self.current_test = Some(fun(self.framebuffer_size)),
-                        },
-                        None => (),
-                    },
-                    _ => (),
-                },
+                }
                 _ => (),
-            },
-            _ => (),
+            }
         }
 
-        match &mut self.current_test {
-            Some(test) => test.handle_event(event, control_flow),
-            _ => (),
+        if let Some(test) = &mut self.current_test {
+            test.handle_event(event, control_flow);
         }
     }
 
     pub fn update(&mut self, delta_time: Duration) {
-        match &mut self.current_test {
-            Some(test) => test.update(delta_time),
-            _ => (),
+        if let Some(test) = &mut self.current_test {
+            test.update(delta_time);
         }
     }
 
-    pub fn render(&self) {
+    pub fn cull_stats(&self) -> Option<CullStats> {
+        self.current_test.as_ref().map(|test| test.cull_stats())
+    }
+
+    pub fn render(&mut self) {
+        self.output_block.bind();
         match &self.current_test {
             Some(test) => test.render(),
             None => unsafe {
                 gl::Clear(gl::COLOR_BUFFER_BIT);
             },
         }
+
+        if let Some(format) = self.pending_screenshot.take() {
+            self.take_screenshot(format);
+        }
     }
 }
 
 pub trait TestScene {
-    fn new(framebuffer_size: (u32, u32)) -> Box<dyn TestScene>
+    // Fails when an asset or shader of the scene cannot be loaded.
+    #[allow(clippy::new_ret_no_self)]
+    fn new(framebuffer_size: (u32, u32)) -> Result<Box<dyn TestScene>, String>
     where
         Self: Sized;
     fn reset(&mut self);
     fn handle_event(&mut self, event: &Event<()>, control_flow: &mut ControlFlow);
     fn update(&mut self, delta: Duration);
     fn render(&self);
+    // Objects skipped by frustum culling during the last render.
+    fn cull_stats(&self) -> CullStats;
     fn set_framebuffer_size(&mut self, size: (u32, u32));
 }
@@ -6,33 +6,82 @@ use cgmath::*;
 use super::*;
 use crate::buffers::*;
 use crate::camera::*;
+use crate::frustum::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::scene_graph::*;
 use crate::shaders::*;
 use crate::textures::*;
 use crate::utils::*;
 
+use std::cell::Cell;
+
 pub struct PbrSpheres {
-    skybox: (VertexArray, VertexBuffer, Shader, TextureCubeMap),
-    spheres: (VertexArray, VertexBuffer, IndexBuffer, Shader),
+    skybox: (Mesh, Shader, TextureCubeMap),
+    spheres: (SceneGraph, Shader),
     pbr_setup: (TextureCubeMap, TextureCubeMap, Texture2D),
     cam: Camera,
+    camera_block: UniformBuffer<CameraBlock>,
     moving_up: bool,
     moving_down: bool,
     moving_right: bool,
     moving_left: bool,
     framebuffer_size: (u32, u32),
+    cull_stats: Cell<CullStats>,
 }
 
 impl PbrSpheres {
     const ENV_MAP_FACE_RESOLUTION: i32 = 1024;
     const LUT_TEXTURE_RESOLUTION: i32 = 512;
 
+    const GRID_ROWS: usize = 7;
+    const GRID_COLS: usize = 7;
+    const GRID_SPACING: f32 = 2.5;
+
     const CAM_SPEED: f32 = 0.00003;
     const FOV_SPEED: f32 = 1.05;
     const MOUSE_SPEED: f32 = 0.002;
+
+    fn setup_spheres_shader(shader: &Shader) {
+        shader.set_uniform_1i("irradiance_map", &0);
+        shader.set_uniform_1i("prefiltered_map", &1);
+        shader.set_uniform_1i("brdf_lut", &2);
+        Material::setup_shader(shader);
+    }
+
+    // Metalness grows along the rows and roughness along the columns. All spheres share
+    // one instanced mesh and differ in their materials.
+    fn grid_graph(rows: usize, cols: usize, spacing: f32) -> SceneGraph {
+        let mut graph = SceneGraph::new();
+        let mesh = graph.add_instanced_mesh(crate_sphere_buffers(1.0), rows * cols);
+        for row in 0..rows {
+            let metallic = row as f32 / rows as f32;
+            for col in 0..cols {
+                let roughness = (col as f32 / cols as f32).clamp(0.05, 1.0);
+                let translation = vec3::<f32>(
+                    col as f32 - (cols as f32 / 2.0),
+                    row as f32 - (rows as f32 / 2.0),
+                    0.0,
+                ) * spacing;
+                let material = graph.add_material(Material {
+                    base_color_factor: vec4(0.5, 0.5, 0.5, 1.0),
+                    metallic_factor: metallic,
+                    roughness_factor: roughness,
+                    ..Material::default()
+                });
+                let node = graph.add_node(None, None, NodeTransform::from_translation(translation));
+                graph.node_mut(node).mesh = Some(NodeMesh::Instanced(mesh));
+                graph.node_mut(node).material = Some(material);
+            }
+        }
+
+        graph.update_world_transforms();
+        graph
+    }
 }
 
 impl TestScene for PbrSpheres {
-    fn new(framebuffer_size: (u32, u32)) -> Box<dyn TestScene> {
+    fn new(framebuffer_size: (u32, u32)) -> Result<Box<dyn TestScene>, String> {
         unsafe {
             gl::Enable(gl::DEPTH_TEST);
             gl::DepthFunc(gl::LESS);
@@ -45,54 +94,54 @@ impl TestScene for PbrSpheres {
             gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
         }
 
-        let skybox_texture = TextureCubeMap::new_from_hdr(
-            "../resources/Factory_Catwalk/Factory_Catwalk_2k.hdr",
-            Self::ENV_MAP_FACE_RESOLUTION,
-        )
-        .unwrap();
+        // Shared by the passes that render into the faces of a cube map.
+        let cube = crate_cube_buffers();
+        let skybox_texture =
+            TextureCubeMap::new_from_hdr(ENVIRONMENT_MAP, Self::ENV_MAP_FACE_RESOLUTION, &cube)?;
+
+        let spheres = {
+            let graph = Self::grid_graph(Self::GRID_ROWS, Self::GRID_COLS, Self::GRID_SPACING);
+            let shader = Shader::new(
+                "../shaders/sphere_pbr_instanced.vert",
+                "../shaders/gltf_pbr_ibl.frag",
+            )?;
+            Self::setup_spheres_shader(&shader);
+            (graph, shader)
+        };
 
         let mut res = Box::new(Self {
             pbr_setup: {
-                let irr = compute_irradiance_map(&skybox_texture);
-                let pref =
-                    compute_prefiltered_env_map(&skybox_texture, Self::ENV_MAP_FACE_RESOLUTION);
+                let irr = compute_irradiance_map(&skybox_texture, &cube)?;
+                let pref = compute_prefiltered_env_map(
+                    &skybox_texture,
+                    Self::ENV_MAP_FACE_RESOLUTION,
+                    &cube,
+                )?;
 
-                let lut = compute_lut_texture(Self::LUT_TEXTURE_RESOLUTION);
+                let lut = compute_lut_texture(Self::LUT_TEXTURE_RESOLUTION)?;
                 (irr, pref, lut)
             },
             skybox: {
-                let (va, vb) = create_skybox_buffers();
-                let shader =
-                    Shader::new("../shaders/skybox.vert", "../shaders/skybox.frag").unwrap();
+                let mesh = create_skybox_buffers();
+                let shader = Shader::new("../shaders/skybox.vert", "../shaders/skybox.frag")?;
 
-                (va, vb, shader, skybox_texture)
-            },
-            spheres: {
-                let (va, vb, ib) = crate_sphere_buffers(1.0);
-                let shader = Shader::new(
-                    "../shaders/sphere_pbr.vert",
-                    "../shaders/sphere_pbr_ibl.frag",
-                )
-                .unwrap();
-                shader.set_uniform_3f("albedo", &vec3(0.5, 0.5, 0.5));
-                shader.set_uniform_1f("ao", &1.0);
-                shader.set_uniform_1i("irradiance_map", &0);
-                shader.set_uniform_1i("prefiltered_map", &1);
-                shader.set_uniform_1i("brdf_lut", &2);
-                (va, vb, ib, shader)
+                (mesh, shader, skybox_texture)
             },
+            spheres,
             cam: Camera::new_default(0.0, 0.0),
+            camera_block: UniformBuffer::new(),
             moving_up: false,
             moving_down: false,
             moving_right: false,
             moving_left: false,
             framebuffer_size,
+            cull_stats: Cell::new(CullStats::default()),
         });
         unsafe {
             gl::Viewport(0, 0, framebuffer_size.0 as i32, framebuffer_size.1 as i32);
         }
         res.reset();
-        res
+        Ok(res)
     }
 
     fn reset(&mut self) {
@@ -105,60 +154,65 @@ impl TestScene for PbrSpheres {
 
     fn handle_event(&mut self, event: &Event<()>, _: &mut ControlFlow) {
         match event {
-            Event::WindowEvent { ref event, .. } => match event {
-                WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
-                    Some(VirtualKeyCode::Up) => {
-                        self.moving_up = match input.state {
-                            ElementState::Pressed => true,
-                            ElementState::Released => false,
-                        };
-                    }
-                    Some(VirtualKeyCode::Down) => {
-                        self.moving_down = match input.state {
-                            ElementState::Pressed => true,
-                            ElementState::Released => false,
-                        };
-                    }
-                    Some(VirtualKeyCode::Right) => {
-                        self.moving_right = match input.state {
-                            ElementState::Pressed => true,
-                            ElementState::Released => false,
-                        };
-                    }
-                    Some(VirtualKeyCode::Left) => {
-                        self.moving_left = match input.state {
-                            ElementState::Pressed => true,
-                            ElementState::Released => false,
-                        };
-                    }
-                    Some(VirtualKeyCode::W) => {
-                        self.cam.perspective.fovy /= Self::FOV_SPEED;
-                        if self.cam.perspective.fovy < Rad::from(Deg(15.0)) {
-                            self.cam.perspective.fovy = Rad::from(Deg(15.0));
-                        }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => match input.virtual_keycode {
+                Some(VirtualKeyCode::Up) => {
+                    self.moving_up = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Down) => {
+                    self.moving_down = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Right) => {
+                    self.moving_right = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::Left) => {
+                    self.moving_left = match input.state {
+                        ElementState::Pressed => true,
+                        ElementState::Released => false,
+                    };
+                }
+                Some(VirtualKeyCode::W) => {
+                    self.cam.perspective.fovy /= Self::FOV_SPEED;
+                    if self.cam.perspective.fovy < Rad::from(Deg(15.0)) {
+                        self.cam.perspective.fovy = Rad::from(Deg(15.0));
                     }
-                    Some(VirtualKeyCode::S) => {
-                        self.cam.perspective.fovy *= Self::FOV_SPEED;
-                        if self.cam.perspective.fovy > Rad::from(Deg(100.0)) {
-                            self.cam.perspective.fovy = Rad::from(Deg(100.0));
-                        }
+                }
+                Some(VirtualKeyCode::S) => {
+                    self.cam.perspective.fovy *= Self::FOV_SPEED;
+                    if self.cam.perspective.fovy > Rad::from(Deg(100.0)) {
+                        self.cam.perspective.fovy = Rad::from(Deg(100.0));
                     }
-                    _ => (),
-                },
-                _ => (),
-            },
-            Event::DeviceEvent { ref event, .. } => match event {
-                DeviceEvent::MouseMotion { delta: (x, y) } => {
-                    self.cam.horizontal_angle -= Rad(*x as f32 * Self::MOUSE_SPEED);
-                    self.cam.vertical_angle -= Rad(*y as f32 * Self::MOUSE_SPEED);
                 }
                 _ => (),
             },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (x, y) },
+                ..
+            } => {
+                self.cam.horizontal_angle -= Rad(*x as f32 * Self::MOUSE_SPEED);
+                self.cam.vertical_angle -= Rad(*y as f32 * Self::MOUSE_SPEED);
+            }
             _ => (),
         }
     }
 
     fn update(&mut self, delta: Duration) {
+        if self.spheres.1.reload_if_changed() {
+            Self::setup_spheres_shader(&self.spheres.1);
+        }
+        self.skybox.1.reload_if_changed();
+
         let mut vel = vec3(0.0, 0.0, 0.0);
         if self.moving_up {
             vel += self.cam.direction();
@@ -179,49 +233,29 @@ impl TestScene for PbrSpheres {
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         }
-        let (view, projection) = self.cam.to_vp();
-        let cam_pos = self.cam.position.to_homogeneous().truncate();
-        let sphere_shader = &self.spheres.3;
+        self.camera_block.update(&self.cam.to_block());
+        self.camera_block.bind();
+        let sphere_shader = &self.spheres.1;
         sphere_shader.bind();
-        sphere_shader.set_uniform_mat4f("projection", &projection);
-        sphere_shader.set_uniform_mat4f("view", &view);
-        sphere_shader.set_uniform_3f("world_cam_posiiton", &cam_pos);
         self.pbr_setup.0.set_slot(&0);
         self.pbr_setup.1.set_slot(&1);
         self.pbr_setup.2.set_slot(&2);
 
-        const ROWS: i32 = 7;
-        const COLS: i32 = 7;
-        const SPACING: f32 = 2.5;
+        let stats = self.spheres.0.draw(sphere_shader, &self.cam.frustum());
+        self.cull_stats.set(stats);
 
-        for row in 0..ROWS {
-            let metallness = row as f32 / ROWS as f32;
-            sphere_shader.set_uniform_1f("metallic", &metallness);
-            for col in 0..COLS {
-                let roughness = (col as f32 / COLS as f32).max(0.05).min(1.0);
-                sphere_shader.set_uniform_1f("roughness", &roughness);
-
-                let translation = vec3::<f32>(
-                    col as f32 - (COLS as f32 / 2.0),
-                    row as f32 - (ROWS as f32 / 2.0),
-                    0.0,
-                ) * SPACING;
-                let model = Matrix4::<f32>::from_translation(translation);
-                sphere_shader.set_uniform_mat4f("model", &model);
-                draw_sphere(&self.spheres.2, &self.spheres.0);
-            }
-        }
-
-        let skybox_shader = &self.skybox.2;
+        let skybox_shader = &self.skybox.1;
         skybox_shader.bind();
-        skybox_shader.set_uniform_mat4f("view", &view);
-        skybox_shader.set_uniform_mat4f("projection", &projection);
         skybox_shader.set_texture_slot("skybox", &0);
-        self.skybox.3.set_slot(&0);
+        self.skybox.2.set_slot(&0);
 
         draw_skybox(&self.skybox.0);
     }
 
+    fn cull_stats(&self) -> CullStats {
+        self.cull_stats.get()
+    }
+
     fn set_framebuffer_size(&mut self, size: (u32, u32)) {
         self.framebuffer_size = size;
 
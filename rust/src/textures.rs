@@ -10,7 +10,8 @@ use std::io::BufReader;
 use cgmath::*;
 use image::*;
 
-use crate::camera::*;
+use crate::buffers::*;
+use crate::mesh::*;
 use crate::shaders::*;
 use crate::utils::*;
 
@@ -68,8 +69,7 @@ fn setup_texture_from_hdr_file(filename: &str) -> Result<(u32, u32), String> {
             .map_err(|_| format!("Cannot read file: {}", filename))?;
         let data_raw = data
             .into_iter()
-            .map(|p| Vec::from(p.channels()))
-            .flatten()
+            .flat_map(|p| Vec::from(p.channels()))
             .collect::<Vec<f32>>();
         let image_buf =
             ImageBuffer::<Rgb<f32>, Vec<f32>>::from_vec(meta.width, meta.height, data_raw)
@@ -104,28 +104,74 @@ impl Texture2D {
 
         unsafe {
             gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        t.set_sampler(gl::REPEAT, gl::REPEAT, gl::LINEAR, gl::LINEAR_MIPMAP_LINEAR);
+        Ok(t)
+    }
+
+    // Tightly packed 8 bit pixels, uploaded without flipping the rows.
+    pub fn new_from_pixels(
+        internal_format: gl::types::GLenum,
+        width: u32,
+        height: u32,
+        format: gl::types::GLenum,
+        pixels: &[u8],
+    ) -> Self {
+        let mut t = Texture2D { id: 0 };
+        unsafe {
+            gl::GenTextures(1, &mut t.id);
+        };
+        t.bind();
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                format,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const c_void,
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        t.set_sampler(gl::REPEAT, gl::REPEAT, gl::LINEAR, gl::LINEAR_MIPMAP_LINEAR);
+        t
+    }
+
+    pub fn set_sampler(
+        &self,
+        wrap_s: gl::types::GLenum,
+        wrap_t: gl::types::GLenum,
+        mag_filter: gl::types::GLenum,
+        min_filter: gl::types::GLenum,
+    ) {
+        self.bind();
+        unsafe {
             gl::TexParameteri(
                 gl::TEXTURE_2D,
                 gl::TEXTURE_WRAP_S,
-                gl::REPEAT.try_into().unwrap(),
+                wrap_s.try_into().unwrap(),
             );
             gl::TexParameteri(
                 gl::TEXTURE_2D,
                 gl::TEXTURE_WRAP_T,
-                gl::REPEAT.try_into().unwrap(),
+                wrap_t.try_into().unwrap(),
             );
             gl::TexParameteri(
                 gl::TEXTURE_2D,
                 gl::TEXTURE_MAG_FILTER,
-                gl::LINEAR.try_into().unwrap(),
+                mag_filter.try_into().unwrap(),
             );
             gl::TexParameteri(
                 gl::TEXTURE_2D,
                 gl::TEXTURE_MIN_FILTER,
-                gl::LINEAR_MIPMAP_LINEAR.try_into().unwrap(),
+                min_filter.try_into().unwrap(),
             );
         }
-        Ok(t)
     }
 
     pub fn new_from_hdr(filename: &str) -> Result<(Self, u32, u32), String> {
@@ -166,6 +212,11 @@ impl Texture2D {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
         }
     }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
     pub fn set_slot(&self, val: &u32) {
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0 + *val);
@@ -274,36 +325,19 @@ impl TextureCubeMap {
         Ok(t)
     }
 
-    pub fn new_from_hdr(filename: &str, face_resolution: i32) -> Result<Self, String> {
+    pub fn new_from_hdr(filename: &str, face_resolution: i32, cube: &Mesh) -> Result<Self, String> {
         let (hdr_texture, _, _) = Texture2D::new_from_hdr(filename)?;
 
-        let mut capture_fbo = 0;
-        let mut capture_rbo = 0;
-        unsafe {
-            gl::GenFramebuffers(1, &mut capture_fbo);
-            gl::GenRenderbuffers(1, &mut capture_rbo);
-
-            gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
-            gl::BindRenderbuffer(gl::RENDERBUFFER, capture_rbo);
-            gl::RenderbufferStorage(
-                gl::RENDERBUFFER,
-                gl::DEPTH_COMPONENT24,
-                face_resolution,
-                face_resolution,
-            );
-            gl::FramebufferRenderbuffer(
-                gl::FRAMEBUFFER,
-                gl::DEPTH_ATTACHMENT,
-                gl::RENDERBUFFER,
-                capture_rbo,
-            );
-        }
+        let capture_rbo =
+            Renderbuffer::new(gl::DEPTH_COMPONENT24, face_resolution, face_resolution);
+        let capture_fbo = Framebuffer::new();
+        capture_fbo.attach_renderbuffer(gl::DEPTH_ATTACHMENT, &capture_rbo);
 
-        let mut env_cubemap = 0;
+        let mut env_cubemap = TextureCubeMap { id: 0 };
         unsafe {
-            gl::GenTextures(1, &mut env_cubemap);
-            gl::BindTexture(gl::TEXTURE_CUBE_MAP, env_cubemap);
+            gl::GenTextures(1, &mut env_cubemap.id);
         }
+        env_cubemap.bind();
         for i in 0..6 {
             unsafe {
                 gl::TexImage2D(
@@ -348,61 +382,82 @@ impl TextureCubeMap {
         }
 
         let conversion_shader =
-            Shader::new("../shaders/hdr_to_cube.vert", "../shaders/hdr_to_cube.frag").unwrap();
+            Shader::new("../shaders/hdr_to_cube.vert", "../shaders/hdr_to_cube.frag")?;
 
         conversion_shader.bind();
         conversion_shader.set_uniform_1i("equirectangular_map", &0);
         conversion_shader.set_uniform_mat4f("projection", &CAPTURE_PERSPECTIVE);
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0);
+        hdr_texture.set_slot(&0);
+
+        {
+            let _binding = capture_fbo.bind_scoped((face_resolution, face_resolution));
+            render_cube_map_faces(&capture_fbo, &env_cubemap, 0, &conversion_shader, cube)?;
         }
-        hdr_texture.bind();
+
+        env_cubemap.bind();
         unsafe {
-            gl::Viewport(0, 0, face_resolution, face_resolution);
-            gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
         }
 
-        let (va, _vb) = crate_cube_buffers();
+        Ok(env_cubemap)
+    }
+
+    pub fn bind(&self) {
         unsafe {
-            gl::FrontFace(gl::CW);
-        }
-        for i in 0..CAPTURE_VIEWS.len() {
-            conversion_shader.set_uniform_mat4f("view", &CAPTURE_VIEWS[i]);
-            unsafe {
-                gl::FramebufferTexture2D(
-                    gl::FRAMEBUFFER,
-                    gl::COLOR_ATTACHMENT0,
-                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-                    env_cubemap,
-                    0,
-                );
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            }
-            draw_cube(&va);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
         }
+    }
 
-        unsafe {
-            gl::FrontFace(gl::CCW);
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
 
-            gl::DeleteFramebuffers(1, &capture_fbo);
-            gl::DeleteRenderbuffers(1, &capture_rbo);
+    pub fn set_slot(&self, val: &u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + *val);
         }
-        let res = TextureCubeMap { id: env_cubemap };
-        res.bind();
+        self.bind();
+    }
+}
+
+impl Drop for TextureCubeMap {
+    fn drop(&mut self) {
         unsafe {
-            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+            gl::DeleteTextures(1, &self.id);
         }
+    }
+}
+
+// Buffer contents exposed to shaders as a samplerBuffer, read with texelFetch.
+pub struct BufferTexture {
+    id: gl::types::GLuint,
+    buffer: VertexBuffer,
+}
 
-        Ok(res)
+impl BufferTexture {
+    pub fn new<T: Copy>(internal_format: gl::types::GLenum, data: &[T]) -> Self {
+        let mut t = Self {
+            id: 0,
+            buffer: VertexBuffer::new_static(data),
+        };
+        unsafe {
+            gl::GenTextures(1, &mut t.id);
+            gl::BindTexture(gl::TEXTURE_BUFFER, t.id);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, internal_format, t.buffer.id());
+        }
+        t
     }
 
     pub fn bind(&self) {
         unsafe {
-            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.id);
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
     pub fn set_slot(&self, val: &u32) {
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0 + *val);
@@ -411,7 +466,7 @@ impl TextureCubeMap {
     }
 }
 
-impl Drop for TextureCubeMap {
+impl Drop for BufferTexture {
     fn drop(&mut self) {
         unsafe {
             gl::DeleteTextures(1, &self.id);
@@ -419,7 +474,34 @@ impl Drop for TextureCubeMap {
     }
 }
 
-pub fn compute_irradiance_map(hdr_enviromental_map: &TextureCubeMap) -> TextureCubeMap {
+fn render_cube_map_faces(
+    framebuffer: &Framebuffer,
+    target: &TextureCubeMap,
+    mip: i32,
+    shader: &Shader,
+    cube: &Mesh,
+) -> Result<(), String> {
+    for (face, view) in CAPTURE_VIEWS.iter().enumerate() {
+        framebuffer.attach_cube_map_face(gl::COLOR_ATTACHMENT0, target, face as u32, mip);
+        framebuffer.check_status()?;
+        shader.bind();
+        shader.set_uniform_mat4f("view", view);
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::FrontFace(gl::CW);
+        }
+        cube.draw();
+        unsafe {
+            gl::FrontFace(gl::CCW);
+        }
+    }
+    Ok(())
+}
+
+pub fn compute_irradiance_map(
+    hdr_enviromental_map: &TextureCubeMap,
+    cube: &Mesh,
+) -> Result<TextureCubeMap, String> {
     let mut irradiance_map = TextureCubeMap { id: 0 };
     unsafe {
         gl::GenTextures(1, &mut irradiance_map.id);
@@ -468,67 +550,32 @@ pub fn compute_irradiance_map(hdr_enviromental_map: &TextureCubeMap) -> TextureC
         );
     }
 
-    let mut capture_fbo = 0;
-    let mut capture_rbo = 0;
-    unsafe {
-        gl::GenFramebuffers(1, &mut capture_fbo);
-        gl::GenRenderbuffers(1, &mut capture_rbo);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
-        gl::BindRenderbuffer(gl::RENDERBUFFER, capture_rbo);
-        gl::RenderbufferStorage(
-            gl::RENDERBUFFER,
-            gl::DEPTH_COMPONENT24,
-            IRR_MAP_SIZE,
-            IRR_MAP_SIZE,
-        );
-    }
+    let capture_rbo = Renderbuffer::new(gl::DEPTH_COMPONENT24, IRR_MAP_SIZE, IRR_MAP_SIZE);
+    let capture_fbo = Framebuffer::new();
+    capture_fbo.attach_renderbuffer(gl::DEPTH_ATTACHMENT, &capture_rbo);
 
     let irradiance_shader = Shader::new(
         "../shaders/hdr_to_cube.vert",
         "../shaders/irradiance_convolution.frag",
-    )
-    .unwrap();
+    )?;
     irradiance_shader.bind();
     irradiance_shader.set_uniform_1i("environmental_map", &0);
     hdr_enviromental_map.set_slot(&0);
     irradiance_shader.set_uniform_mat4f("projection", &CAPTURE_PERSPECTIVE);
 
-    unsafe {
-        gl::Viewport(0, 0, IRR_MAP_SIZE, IRR_MAP_SIZE);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
-        gl::FrontFace(gl::CW);
-    }
-    let (va, _vb) = crate_cube_buffers();
-    for i in 0..CAPTURE_VIEWS.len() {
-        irradiance_shader.set_uniform_mat4f("view", &CAPTURE_VIEWS[i]);
-        unsafe {
-            gl::FramebufferTexture2D(
-                gl::FRAMEBUFFER,
-                gl::COLOR_ATTACHMENT0,
-                gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-                irradiance_map.id,
-                0,
-            );
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        }
-        irradiance_shader.bind();
-        draw_cube(&va);
+    {
+        let _binding = capture_fbo.bind_scoped((IRR_MAP_SIZE, IRR_MAP_SIZE));
+        render_cube_map_faces(&capture_fbo, &irradiance_map, 0, &irradiance_shader, cube)?;
     }
 
-    unsafe {
-        gl::FrontFace(gl::CCW);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-        gl::DeleteFramebuffers(1, &capture_fbo);
-        gl::DeleteRenderbuffers(1, &capture_rbo);
-    }
-
-    irradiance_map
+    Ok(irradiance_map)
 }
 
 pub fn compute_prefiltered_env_map(
     hdr_enviromental_map: &TextureCubeMap,
     face_resolution: i32,
-) -> TextureCubeMap {
+    cube: &Mesh,
+) -> Result<TextureCubeMap, String> {
     let mut prefiltered_env_map = TextureCubeMap { id: 0 };
     unsafe {
         gl::GenTextures(1, &mut prefiltered_env_map.id);
@@ -580,66 +627,38 @@ pub fn compute_prefiltered_env_map(
     let shader = Shader::new(
         "../shaders/hdr_to_cube.vert",
         "../shaders/prefiltered_env_map.frag",
-    )
-    .unwrap();
+    )?;
     const ENV_MAP_SLOT: i32 = 0;
     shader.set_uniform_1i("environmental_map", &ENV_MAP_SLOT);
     hdr_enviromental_map.set_slot(&(ENV_MAP_SLOT as u32));
     shader.set_uniform_mat4f("projection", &CAPTURE_PERSPECTIVE);
     shader.set_uniform_1i("env_map_resolution", &face_resolution);
 
-    let mut capture_fbo = 0;
-    let mut capture_rbo = 0;
-    unsafe {
-        gl::GenFramebuffers(1, &mut capture_fbo);
-        gl::GenRenderbuffers(1, &mut capture_rbo);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
-    }
+    let capture_rbo = Renderbuffer::new(
+        gl::DEPTH_COMPONENT24,
+        PREFILTERED_MAP_SIZE,
+        PREFILTERED_MAP_SIZE,
+    );
+    let capture_fbo = Framebuffer::new();
+    capture_fbo.attach_renderbuffer(gl::DEPTH_ATTACHMENT, &capture_rbo);
+
     const MAX_MPS_LEVELS: i32 = 5;
     for mip in 0..MAX_MPS_LEVELS {
-        let mip_width = 128 / (2 as i32).pow(mip as u32);
-        let mip_height = 128 / (2 as i32).pow(mip as u32);
-        unsafe {
-            gl::BindRenderbuffer(gl::RENDERBUFFER, capture_rbo);
-            gl::RenderbufferStorage(
-                gl::RENDERBUFFER,
-                gl::DEPTH_COMPONENT24,
-                mip_width,
-                mip_height,
-            );
-            gl::FrontFace(gl::CW);
-            gl::Viewport(0, 0, mip_width, mip_height);
-        }
+        let mip_width = PREFILTERED_MAP_SIZE / 2_i32.pow(mip as u32);
+        let mip_height = PREFILTERED_MAP_SIZE / 2_i32.pow(mip as u32);
+        capture_rbo.set_storage(gl::DEPTH_COMPONENT24, mip_width, mip_height);
 
         let roughness = mip as f32 / (MAX_MPS_LEVELS - 1) as f32;
         shader.set_uniform_1f("roughness", &roughness);
-        let (va, _vb) = crate_cube_buffers();
-        for i in 0..CAPTURE_VIEWS.len() {
-            shader.set_uniform_mat4f("view", &CAPTURE_VIEWS[i]);
-            unsafe {
-                gl::FramebufferTexture2D(
-                    gl::FRAMEBUFFER,
-                    gl::COLOR_ATTACHMENT0,
-                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-                    prefiltered_env_map.id,
-                    mip,
-                );
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            }
-            draw_cube(&va);
-        }
-    }
-    unsafe {
-        gl::FrontFace(gl::CCW);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-        gl::DeleteFramebuffers(1, &capture_fbo);
-        gl::DeleteRenderbuffers(1, &capture_rbo);
+
+        let _binding = capture_fbo.bind_scoped((mip_width, mip_height));
+        render_cube_map_faces(&capture_fbo, &prefiltered_env_map, mip, &shader, cube)?;
     }
 
-    prefiltered_env_map
+    Ok(prefiltered_env_map)
 }
 
-pub fn compute_lut_texture(resolution: i32) -> Texture2D {
+pub fn compute_lut_texture(resolution: i32) -> Result<Texture2D, String> {
     let mut id = 0;
     unsafe {
         gl::GenTextures(1, &mut id);
@@ -664,42 +683,22 @@ pub fn compute_lut_texture(resolution: i32) -> Texture2D {
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
     }
 
-    let mut capture_fbo = 0;
-    let mut capture_rbo = 0;
-    unsafe {
-        gl::GenFramebuffers(1, &mut capture_fbo);
-        gl::GenRenderbuffers(1, &mut capture_rbo);
-
-        gl::BindFramebuffer(gl::FRAMEBUFFER, capture_fbo);
-        gl::BindRenderbuffer(gl::RENDERBUFFER, capture_rbo);
-        gl::RenderbufferStorage(
-            gl::RENDERBUFFER,
-            gl::DEPTH_COMPONENT24,
-            resolution,
-            resolution,
-        );
-        gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT0,
-            gl::TEXTURE_2D,
-            res.id,
-            0,
-        );
-        gl::Viewport(0, 0, resolution, resolution);
-    }
+    let capture_rbo = Renderbuffer::new(gl::DEPTH_COMPONENT24, resolution, resolution);
+    let capture_fbo = Framebuffer::new();
+    capture_fbo.attach_renderbuffer(gl::DEPTH_ATTACHMENT, &capture_rbo);
+    capture_fbo.attach_texture_2d(gl::COLOR_ATTACHMENT0, &res, 0);
+    capture_fbo.check_status()?;
 
-    let shader = Shader::new("../shaders/lut_texture.vert", "../shaders/lut_texture.frag").unwrap();
+    let shader = Shader::new("../shaders/lut_texture.vert", "../shaders/lut_texture.frag")?;
     shader.bind();
-    let (va, _vb) = create_quad_buffers();
-    unsafe {
-        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    let quad = create_quad_buffers();
+    {
+        let _binding = capture_fbo.bind_scoped((resolution, resolution));
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        quad.draw();
     }
-    draw_quad(&va);
 
-    unsafe {
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-        gl::DeleteFramebuffers(1, &capture_fbo);
-        gl::DeleteRenderbuffers(1, &capture_rbo);
-    }
-    res
+    Ok(res)
 }
@@ -1,221 +1,124 @@
 extern crate cgmath;
-extern crate obj;
 
 use crate::buffers::*;
+use crate::context::*;
+use crate::mesh::*;
+use crate::procedural::*;
 
 use cgmath::*;
-use obj::*;
 
-use std::fs::File;
-use std::io::BufReader;
-
-pub fn crate_sphere_buffers(radius: f32) -> (VertexArray, VertexBuffer, IndexBuffer) {
-    const X_SEGMENTS: u32 = 64;
-    const Y_SEGMENTS: u32 = 64;
+// Per-instance data of the instanced PBR shaders, starting after the mesh attributes.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PbrInstance {
+    pub model: [[f32; 4]; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+}
 
-    let mut positions = Vec::new();
-    let mut uvs = Vec::new();
-    let mut normals = Vec::new();
-    for y in 0..=Y_SEGMENTS {
-        for x in 0..=X_SEGMENTS {
-            let x_seg = x as f32 / X_SEGMENTS as f32;
-            let y_seg = y as f32 / Y_SEGMENTS as f32;
-            use std::f32::consts::PI;
-            let x_pos = (x_seg * 2.0 * PI).cos() * (y_seg * PI).sin();
-            let y_pos = (y_seg * PI).cos();
-            let z_pos = (x_seg * 2.0 * PI).sin() * (y_seg * PI).sin();
-            positions.push(Vector3 {
-                x: radius * x_pos,
-                y: radius * y_pos,
-                z: radius * z_pos,
-            });
-            uvs.push(Vector2 { x: x_seg, y: y_seg });
-            normals.push(Vector3 {
-                x: x_pos,
-                y: y_pos,
-                z: z_pos,
-            });
-        }
+impl Vertex for PbrInstance {
+    fn layout() -> VertexLayout {
+        VertexLayout::new().mat4(4).float(8, 2).per_instance()
     }
-    let mut indices = Vec::<u32>::new();
-    let mut odd_row = false;
-    for y in 0..Y_SEGMENTS {
-        if odd_row {
-            for x in 0..=X_SEGMENTS {
-                indices.push((y + 1) * (X_SEGMENTS + 1) + x);
-                indices.push(y * (X_SEGMENTS + 1) + x);
-            }
+}
+
+// Per-instance attributes rewritten every frame. They are streamed through a
+// persistently mapped buffer when the context supports it, otherwise a dynamic buffer is
+// orphaned and refilled.
+pub enum InstanceBuffer {
+    Dynamic(VertexBuffer),
+    Stream(StreamBuffer),
+}
+
+impl InstanceBuffer {
+    // Attaches storage for `capacity` instances of `T` to a vertex array which already
+    // has its per-vertex attributes set up.
+    pub fn new<T: Vertex>(va: &VertexArray, capacity: usize) -> Self {
+        let size = capacity.max(1) * std::mem::size_of::<T>();
+        va.bind();
+        let buffer = if gl_supports((4, 2), "GL_ARB_base_instance") {
+            StreamBuffer::new(gl::ARRAY_BUFFER, size).ok()
         } else {
-            for x in (0..=X_SEGMENTS).rev() {
-                indices.push(y * (X_SEGMENTS + 1) + x);
-                indices.push((y + 1) * (X_SEGMENTS + 1) + x);
+            None
+        };
+        let buffer = match buffer {
+            Some(buffer) => {
+                buffer.bind();
+                Self::Stream(buffer)
             }
-        }
-        odd_row = !odd_row;
+            None => {
+                let buffer = VertexBuffer::new_stream(size);
+                buffer.bind();
+                Self::Dynamic(buffer)
+            }
+        };
+        va.set_vertex_type::<T>();
+        buffer
     }
 
-    let data = positions
-        .into_iter()
-        .zip(uvs.into_iter())
-        .zip(normals.into_iter())
-        .map(|((pos, uv), norm)| vec![pos.x, pos.y, pos.z, uv.x, uv.y, norm.x, norm.y, norm.z])
-        .flatten()
-        .collect::<Vec<f32>>();
-
-    let va = VertexArray::new();
-    va.bind();
-    let vb = VertexBuffer::new_static(&data);
-    vb.bind();
-    const STRIDE: i32 = 3 + 2 + 3;
-    va.set_vertex_attrib_array(0, 3, false, STRIDE, 0);
-    va.set_vertex_attrib_array(1, 2, false, STRIDE, 3);
-    va.set_vertex_attrib_array(2, 3, false, STRIDE, 5);
-    let ib = IndexBuffer::new_static(&indices);
-
-    (va, vb, ib)
-}
-
-pub fn draw_sphere(sphere_ib: &IndexBuffer, sphere_va: &VertexArray) {
-    sphere_va.bind();
-    sphere_ib.bind();
-    unsafe {
-        gl::DrawElements(
-            gl::TRIANGLE_STRIP,
-            sphere_ib.count() as i32,
-            gl::UNSIGNED_INT,
-            std::ptr::null(),
-        );
+    // Returns the index of the first written instance, to be drawn from.
+    pub fn write<T: Vertex>(&mut self, instances: &[T]) -> usize {
+        match self {
+            Self::Dynamic(buffer) => {
+                buffer.orphan();
+                buffer.update_sub_data(0, instances);
+                0
+            }
+            Self::Stream(buffer) => buffer.write(instances) / std::mem::size_of::<T>(),
+        }
     }
 }
 
-pub fn crate_cube_buffers() -> (VertexArray, VertexBuffer) {
-    #[rustfmt::skip]
-    const VERTICES: [f32; 8*6*6] = [
-        // back face
-        -1.0, -1.0, -1.0,  0.0, 0.0,  0.0,  0.0, -1.0, // bottom-left
-         1.0,  1.0, -1.0,  1.0, 1.0,  0.0,  0.0, -1.0, // top-right
-         1.0, -1.0, -1.0,  1.0, 0.0,  0.0,  0.0, -1.0, // bottom-right         
-         1.0,  1.0, -1.0,  1.0, 1.0,  0.0,  0.0, -1.0, // top-right
-        -1.0, -1.0, -1.0,  0.0, 0.0,  0.0,  0.0, -1.0, // bottom-left
-        -1.0,  1.0, -1.0,  0.0, 1.0,  0.0,  0.0, -1.0, // top-left
-        // front face
-        -1.0, -1.0,  1.0,  0.0, 0.0,  0.0,  0.0,  1.0, // bottom-left
-         1.0, -1.0,  1.0,  1.0, 0.0,  0.0,  0.0,  1.0, // bottom-right
-         1.0,  1.0,  1.0,  1.0, 1.0,  0.0,  0.0,  1.0, // top-right
-         1.0,  1.0,  1.0,  1.0, 1.0,  0.0,  0.0,  1.0, // top-right
-        -1.0,  1.0,  1.0,  0.0, 1.0,  0.0,  0.0,  1.0, // top-left
-        -1.0, -1.0,  1.0,  0.0, 0.0,  0.0,  0.0,  1.0, // bottom-left
-        // left face
-        -1.0,  1.0,  1.0,  1.0, 0.0, -1.0,  0.0,  0.0, // top-right
-        -1.0,  1.0, -1.0,  1.0, 1.0, -1.0,  0.0,  0.0, // top-left
-        -1.0, -1.0, -1.0,  0.0, 1.0, -1.0,  0.0,  0.0, // bottom-left
-        -1.0, -1.0, -1.0,  0.0, 1.0, -1.0,  0.0,  0.0, // bottom-left
-        -1.0, -1.0,  1.0,  0.0, 0.0, -1.0,  0.0,  0.0, // bottom-right
-        -1.0,  1.0,  1.0,  1.0, 0.0, -1.0,  0.0,  0.0, // top-right
-        // right face
-         1.0,  1.0,  1.0,  1.0, 0.0,  1.0,  0.0,  0.0, // top-left
-         1.0, -1.0, -1.0,  0.0, 1.0,  1.0,  0.0,  0.0, // bottom-right
-         1.0,  1.0, -1.0,  1.0, 1.0,  1.0,  0.0,  0.0, // top-right         
-         1.0, -1.0, -1.0,  0.0, 1.0,  1.0,  0.0,  0.0, // bottom-right
-         1.0,  1.0,  1.0,  1.0, 0.0,  1.0,  0.0,  0.0, // top-left
-         1.0, -1.0,  1.0,  0.0, 0.0,  1.0,  0.0,  0.0, // bottom-left     
-        // bottom face
-        -1.0, -1.0, -1.0,  0.0, 1.0,  0.0, -1.0,  0.0, // top-right
-         1.0, -1.0, -1.0,  1.0, 1.0,  0.0, -1.0,  0.0, // top-left
-         1.0, -1.0,  1.0,  1.0, 0.0,  0.0, -1.0,  0.0, // bottom-left
-         1.0, -1.0,  1.0,  1.0, 0.0,  0.0, -1.0,  0.0, // bottom-left
-        -1.0, -1.0,  1.0,  0.0, 0.0,  0.0, -1.0,  0.0, // bottom-right
-        -1.0, -1.0, -1.0,  0.0, 1.0,  0.0, -1.0,  0.0, // top-right
-        // top face
-        -1.0,  1.0, -1.0,  0.0, 1.0,  0.0,  1.0,  0.0, // top-left
-         1.0,  1.0,  1.0,  1.0, 0.0,  0.0,  1.0,  0.0, // bottom-right
-         1.0,  1.0, -1.0,  1.0, 1.0,  0.0,  1.0,  0.0, // top-right     
-         1.0,  1.0,  1.0,  1.0, 0.0,  0.0,  1.0,  0.0, // bottom-right
-        -1.0,  1.0, -1.0,  0.0, 1.0,  0.0,  1.0,  0.0, // top-left
-        -1.0,  1.0,  1.0,  0.0, 0.0,  0.0,  1.0,  0.0 // bottom-left        
-    ];
-    let va = VertexArray::new();
-    va.bind();
-    let vb = VertexBuffer::new_static(&VERTICES);
-    vb.bind();
-    const STRIDE: i32 = 3 + 2 + 3;
-    va.set_vertex_attrib_array(0, 3, false, STRIDE, 0);
-    va.set_vertex_attrib_array(1, 2, false, STRIDE, 3);
-    va.set_vertex_attrib_array(2, 3, false, STRIDE, 5);
-    (va, vb)
+// Uploads `instances` and draws `mesh` once per instance, the buffer must have been
+// created on the mesh's vertex array.
+pub fn draw_mesh_instanced<T: Vertex>(
+    mesh: &Mesh,
+    instance_buffer: &mut InstanceBuffer,
+    instances: &[T],
+) {
+    let first_instance = instance_buffer.write(instances);
+    mesh.draw_instanced_from(first_instance, instances.len());
 }
 
-pub fn draw_cube(va: &VertexArray) {
-    va.bind();
-    unsafe {
-        gl::DrawArrays(gl::TRIANGLES, 0, 36);
-    }
+pub fn draw_sphere_instanced<T: Vertex>(
+    sphere: &Mesh,
+    instance_buffer: &mut InstanceBuffer,
+    instances: &[T],
+) {
+    draw_mesh_instanced(sphere, instance_buffer, instances);
 }
 
-pub fn create_skybox_buffers() -> (VertexArray, VertexBuffer) {
-    #[rustfmt::skip]
-    const VERTICES: [f32; 6*6*3] = [
-        -1.0,  1.0,  -1.0,
-        -1.0, -1.0,  -1.0,
-         1.0, -1.0,  -1.0,
-         1.0, -1.0,  -1.0,
-         1.0,  1.0,  -1.0,
-        -1.0,  1.0,  -1.0,
-
-        -1.0, -1.0,  1.0,
-        -1.0, -1.0, -1.0,
-        -1.0,  1.0, -1.0,
-        -1.0,  1.0, -1.0,
-        -1.0,  1.0,  1.0,
-        -1.0, -1.0,  1.0,
-
-         1.0, -1.0, -1.0,
-         1.0, -1.0,  1.0,
-         1.0,  1.0,  1.0,
-         1.0,  1.0,  1.0,
-         1.0,  1.0, -1.0,
-         1.0, -1.0, -1.0,
-
-        -1.0, -1.0,  1.0,
-        -1.0,  1.0,  1.0,
-         1.0,  1.0,  1.0,
-         1.0,  1.0,  1.0,
-         1.0, -1.0,  1.0,
-        -1.0, -1.0,  1.0,
+pub fn draw_cube_instanced<T: Vertex>(
+    cube: &Mesh,
+    instance_buffer: &mut InstanceBuffer,
+    instances: &[T],
+) {
+    draw_mesh_instanced(cube, instance_buffer, instances);
+}
 
-        -1.0,  1.0, -1.0,
-         1.0,  1.0, -1.0,
-         1.0,  1.0,  1.0,
-         1.0,  1.0,  1.0,
-        -1.0,  1.0,  1.0,
-        -1.0,  1.0, -1.0,
+pub fn crate_sphere_buffers(radius: f32) -> Mesh {
+    Mesh::new(&uv_sphere(radius, 64, 64))
+}
 
-        -1.0, -1.0, -1.0,
-        -1.0, -1.0,  1.0,
-         1.0, -1.0, -1.0,
-         1.0, -1.0, -1.0,
-        -1.0, -1.0,  1.0,
-         1.0, -1.0,  1.0];
+pub fn crate_cube_buffers() -> Mesh {
+    Mesh::new(&cuboid(vec3(1.0, 1.0, 1.0)))
+}
 
-    let va = VertexArray::new();
-    va.bind();
-    let vb = VertexBuffer::new_static(&VERTICES);
-    vb.bind();
-    const STRIDE: i32 = 3;
-    va.set_vertex_attrib_array(0, 3, false, STRIDE, 0);
-    (va, vb)
+pub fn create_skybox_buffers() -> Mesh {
+    Mesh::new(&inverted(cuboid(vec3(1.0, 1.0, 1.0))))
 }
 
-pub fn draw_skybox(va: &VertexArray) {
-    va.bind();
+pub fn draw_skybox(mesh: &Mesh) {
     unsafe {
         gl::DepthFunc(gl::LEQUAL);
-        gl::DrawArrays(gl::TRIANGLES, 0, 36);
+    }
+    mesh.draw();
+    unsafe {
         gl::DepthFunc(gl::LESS);
     }
 }
 
-pub fn create_quad_buffers() -> (VertexArray, VertexBuffer) {
+pub fn create_quad_buffers() -> Mesh {
     #[rustfmt::skip]
     const VERTICES: [f32; 4* 5] = [
         -1.0,  1.0, 0.0,    0.0, 1.0,
@@ -223,67 +126,18 @@ pub fn create_quad_buffers() -> (VertexArray, VertexBuffer) {
          1.0,  1.0, 0.0,    1.0, 1.0,
          1.0, -1.0, 0.0,    1.0, 0.0,
     ];
-    let va = VertexArray::new();
-    va.bind();
-    let vb = VertexBuffer::new_static(&VERTICES);
-    vb.bind();
-    const STRIDE: i32 = 5;
-    va.set_vertex_attrib_array(0, 3, false, STRIDE, 0);
-    va.set_vertex_attrib_array(1, 2, false, STRIDE, 3);
-    (va, vb)
-}
-
-pub fn draw_quad(va: &VertexArray) {
-    va.bind();
-    unsafe {
-        gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
-    }
-}
-
-pub fn load_model(obj_filename: &str) -> Result<(VertexArray, VertexBuffer, IndexBuffer), String> {
-    let input = BufReader::new(
-        File::open(obj_filename).map_err(|_| format!("Failed to load model: {}", obj_filename))?,
-    );
-    let model: Obj<TexturedVertex, u32> =
-        load_obj(input).map_err(|_| format!("Failed to parse model: {}", obj_filename))?;
-    let va = VertexArray::new();
-    va.bind();
-    let vertices: Vec<f32> = model
-        .vertices
-        .into_iter()
-        .flat_map(|v| {
-            vec![
-                v.position[0],
-                v.position[1],
-                v.position[2],
-                v.texture[0],
-                v.texture[1],
-                v.normal[0],
-                v.normal[1],
-                v.normal[2],
-            ]
+    let vertices = VERTICES
+        .chunks_exact(5)
+        .map(|v| MeshVertex {
+            position: [v[0], v[1], v[2]],
+            uv: [v[3], v[4]],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
         })
-        .collect();
-    let vb = VertexBuffer::new_static(&vertices);
-    vb.bind();
-    const STRIDE: i32 = 3 + 3 + 2;
-    va.set_vertex_attrib_array(0, 3, false, STRIDE, 0);
-    va.set_vertex_attrib_array(1, 2, false, STRIDE, 3);
-    va.set_vertex_attrib_array(2, 3, false, STRIDE, 5);
-    let ib = IndexBuffer::new_static(&model.indices);
-    ib.bind();
-    Ok((va, vb, ib))
-}
-
-pub fn draw_model(sphere_ib: &IndexBuffer, sphere_va: &VertexArray) {
-    sphere_va.bind();
-    sphere_ib.bind();
-    unsafe {
-        gl::DrawElements(
-            gl::TRIANGLES,
-            sphere_ib.count() as i32,
-            gl::UNSIGNED_INT,
-            std::ptr::null(),
-        );
-    }
+        .collect::<Vec<_>>();
+    Mesh::new(&MeshData {
+        topology: gl::TRIANGLE_STRIP,
+        vertices,
+        indices: None,
+    })
 }
@@ -0,0 +1,25 @@
+const float PI = 3.14159265359;
+
+float normal_distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float bracket = n_dot_h * n_dot_h * ( a * a - 1.0) + 1.0;
+    return a * a / (PI * bracket* bracket);
+}
+
+float gemoetry_funciton_schlick_ggx(float dot_prod, float k){
+    return dot_prod / (dot_prod * (1.0 - k) + k);
+}
+
+float geometry_funciton_smith(float n_dot_v, float n_dot_l, float k) {
+    float ggx1 = gemoetry_funciton_schlick_ggx(n_dot_v, k);
+    float ggx2 = gemoetry_funciton_schlick_ggx(n_dot_l, k);
+    return ggx1 * ggx2;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 F0){
+    return F0 + (1.0 - F0) * pow(1.0 - cos_theta, 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 F0, float roughness) {
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - cos_theta, 5.0);
+}
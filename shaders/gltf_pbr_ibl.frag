@@ -0,0 +1,200 @@
+#version 330
+
+in vec2 uv;
+in vec2 uv1;
+in vec3 world_position;
+in vec3 world_normal;
+in vec4 world_tangent;
+// Per-instance scale of the metallic and roughness factors, 1 when not instanced.
+flat in vec2 metallic_roughness_scale;
+
+out vec4 fragment_color;
+
+// glTF metallic-roughness material. Base color and emissive textures are sRGB and
+// decoded by the sampler, metallic and roughness come from the B and G channels.
+uniform vec4 base_color_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform vec3 emissive_factor;
+uniform float normal_scale;
+uniform float occlusion_strength;
+
+uniform sampler2D base_color_map;
+uniform sampler2D metallic_roughness_map;
+uniform sampler2D normal_map;
+uniform sampler2D occlusion_map;
+uniform sampler2D emissive_map;
+
+uniform bool has_base_color_map;
+uniform bool has_metallic_roughness_map;
+uniform bool has_normal_map;
+uniform bool has_occlusion_map;
+uniform bool has_emissive_map;
+
+// KHR_texture_transform, identity when the extension is not used.
+uniform mat3 base_color_uv_transform;
+uniform mat3 metallic_roughness_uv_transform;
+uniform mat3 normal_uv_transform;
+uniform mat3 occlusion_uv_transform;
+uniform mat3 emissive_uv_transform;
+
+// TEXCOORD_n set of every texture, 0 or 1.
+uniform int base_color_uv_set;
+uniform int metallic_roughness_uv_set;
+uniform int normal_uv_set;
+uniform int occlusion_uv_set;
+uniform int emissive_uv_set;
+
+// Lights of the scene graph, on top of the image based lighting. Point lights pass
+// their position with w = 1, directional lights the direction they shine in with w = 0.
+#define MAX_LIGHTS 4
+uniform int light_count;
+uniform vec4 light_vectors[MAX_LIGHTS];
+uniform vec3 light_colors[MAX_LIGHTS];
+
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D brdf_lut;
+
+#include "camera.glsl"
+
+#include "brdf.glsl"
+#include "tonemap.glsl"
+
+vec2 transform_uv(mat3 transform, int uv_set) {
+    return (transform * vec3(uv_set == 1 ? uv1 : uv, 1.0)).xy;
+}
+
+// Tangent frame from the screen space derivatives of the position and uv, for
+// primitives without a usable tangent attribute.
+mat3 cotangent_frame(vec3 N, vec3 p, vec2 uv) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, N);
+    vec3 dp1perp = cross(N, dp1);
+    vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float max_length_squared = max(dot(T, T), dot(B, B));
+    if (max_length_squared <= 0.0) {
+        return mat3(vec3(0.0), vec3(0.0), N);
+    }
+    float inv_max = inversesqrt(max_length_squared);
+    return mat3(T * inv_max, B * inv_max, N);
+}
+
+vec3 get_normal_worldspace() {
+    vec3 N = normalize(world_normal);
+    if (!has_normal_map) {
+        return N;
+    }
+    vec2 normal_uv = transform_uv(normal_uv_transform, normal_uv_set);
+    vec3 normal_tangentspace = texture(normal_map, normal_uv).xyz * 2.0 - 1.0;
+    normal_tangentspace.xy *= normal_scale;
+
+    // Derivatives are undefined in non-uniform control flow, so the fallback frame is
+    // computed before choosing between the two.
+    mat3 derivative_TBN = cotangent_frame(N, world_position, normal_uv);
+
+    // Missing tangents read as (0, 0, 0, 1) and must not be normalized.
+    vec3 tangent = world_tangent.xyz - dot(world_tangent.xyz, N) * N;
+    mat3 TBN;
+    if (length(tangent) > 1e-6) {
+        vec3 T = normalize(tangent);
+        vec3 B = cross(N, T) * world_tangent.w;
+        TBN = mat3(T, B, N);
+    } else {
+        TBN = derivative_TBN;
+    }
+
+    vec3 normal = TBN * normal_tangentspace;
+    return length(normal) > 0.0 ? normalize(normal) : N;
+}
+
+void main() {
+    vec3 N = get_normal_worldspace();
+    vec3 V = normalize(camera_position.xyz - world_position);
+    vec3 R = reflect(-V, N);
+
+    vec4 base_color = base_color_factor;
+    if (has_base_color_map) {
+        base_color *=
+            texture(base_color_map, transform_uv(base_color_uv_transform, base_color_uv_set));
+    }
+    vec3 albedo = base_color.rgb;
+
+    float metallic = metallic_factor * metallic_roughness_scale.x;
+    float roughness = roughness_factor * metallic_roughness_scale.y;
+    if (has_metallic_roughness_map) {
+        vec2 metallic_roughness_uv =
+            transform_uv(metallic_roughness_uv_transform, metallic_roughness_uv_set);
+        vec4 metallic_roughness = texture(metallic_roughness_map, metallic_roughness_uv);
+        metallic *= metallic_roughness.b;
+        roughness *= metallic_roughness.g;
+    }
+
+    float ao = 1.0;
+    if (has_occlusion_map) {
+        vec2 occlusion_uv = transform_uv(occlusion_uv_transform, occlusion_uv_set);
+        ao = 1.0 + occlusion_strength * (texture(occlusion_map, occlusion_uv).r - 1.0);
+    }
+
+    vec3 emissive = emissive_factor;
+    if (has_emissive_map) {
+        emissive *= texture(emissive_map, transform_uv(emissive_uv_transform, emissive_uv_set)).rgb;
+    }
+
+    vec3 F0 = vec3(0.04);
+    F0 = mix(F0, albedo, metallic);
+
+    float n_dot_v = max(dot(N, V), 0.0);
+
+    float k_direct = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    vec3 Lo = vec3(0.0);
+    for (int i = 0; i < min(light_count, MAX_LIGHTS); ++i) {
+        vec3 L;
+        vec3 radiance = light_colors[i];
+        if (light_vectors[i].w == 0.0) {
+            L = -light_vectors[i].xyz;
+        } else {
+            vec3 to_light = light_vectors[i].xyz - world_position;
+            L = normalize(to_light);
+            radiance /= max(dot(to_light, to_light), 0.0001);
+        }
+        vec3 H = normalize(V + L);
+
+        float n_dot_h = max(dot(N, H), 0.0);
+        float n_dot_l = max(dot(N, L), 0.0);
+        float h_dot_v = max(dot(H, V), 0.0);
+
+        float n_specular = normal_distribution_ggx(n_dot_h, roughness);
+        float d_specular = geometry_funciton_smith(n_dot_v, n_dot_l, k_direct);
+        vec3 ks_direct = fresnel_schlick(h_dot_v, F0);
+        float f_cook_torrance = n_specular * d_specular / max(4.0 * n_dot_v * n_dot_l, 0.001);
+
+        vec3 kd_direct = (vec3(1.0) - ks_direct) * (1.0 - metallic);
+        Lo += (kd_direct * albedo / PI + ks_direct * f_cook_torrance) * radiance * n_dot_l;
+    }
+
+    vec3 ks = fresnel_schlick_roughness(n_dot_v, F0, roughness);
+    vec3 kd = 1.0 - ks;
+    kd *= (1.0 - metallic);
+
+    vec3 irradiance = texture(irradiance_map, N).rgb;
+    vec3 diffuse = kd * irradiance * albedo;
+
+    const float MAX_REFLECTION_LOD = 4;
+    float lod_level = roughness * MAX_REFLECTION_LOD;
+    vec3 prefiltered_color = textureLod(prefiltered_map, R, lod_level).rgb;
+    vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+    vec3 specular = prefiltered_color * (ks * brdf.x + brdf.y);
+
+    vec3 ambient = (diffuse  + specular) * ao;
+
+    vec3 color = ambient + Lo + emissive;
+
+    fragment_color = vec4(tonemap(color), base_color.a);
+}
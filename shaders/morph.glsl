@@ -0,0 +1,20 @@
+// Has to match MAX_MORPH_TARGETS in src/gltf_loader.rs.
+#define MAX_MORPH_TARGETS 8
+
+// Three texels per vertex and target: position, normal and tangent delta.
+uniform samplerBuffer morph_targets;
+uniform int morph_target_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+const int MORPH_POSITION = 0;
+const int MORPH_NORMAL = 1;
+const int MORPH_TANGENT = 2;
+
+vec3 morph_delta(int property) {
+    vec3 delta = vec3(0.0);
+    for (int i = 0; i < morph_target_count; i++) {
+        int texel = (gl_VertexID * morph_target_count + i) * 3 + property;
+        delta += morph_weights[i] * texelFetch(morph_targets, texel).xyz;
+    }
+    return delta;
+}
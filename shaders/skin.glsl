@@ -0,0 +1,4 @@
+// The array size has to match MAX_JOINTS in src/animation.rs.
+layout (std140) uniform Skin {
+    mat4 joint_matrices[128];
+};
@@ -5,10 +5,10 @@ in vec3 tex_coord;
 
 uniform samplerCube skybox;
 
+#include "tonemap.glsl"
+
 void main() {    
     vec3 color = texture(skybox, tex_coord).rgb;
-    color = color / (color + vec3(1.0));
-    color = pow(color, vec3(1.0/2.2)); 
 
-    fragment_color = vec4(color, 1.0);
-}
\ No newline at end of file
+    fragment_color = vec4(tonemap(color), 1.0);
+}
@@ -3,8 +3,7 @@ layout (location = 0) in vec3 position;
 
 out vec3 tex_coord;
 
-uniform mat4 projection;
-uniform mat4 view;
+#include "camera.glsl"
 
 void main() {
     tex_coord = position;
@@ -14,38 +14,18 @@ uniform samplerCube irradiance_map;
 uniform samplerCube prefiltered_map;
 uniform sampler2D brdf_lut;
 
-uniform vec3 world_cam_posiiton;
+#include "camera.glsl"
 
 #define LIGHT_COUNT 4
 
 uniform vec3 light_positions[LIGHT_COUNT];
 uniform vec3 light_colors[LIGHT_COUNT];
 
-const float PI = 3.14159265359;
-
-float normal_distribution_ggx(float n_dot_h, float roughness) {
-    float a = roughness * roughness;
-    float bracket = n_dot_h * n_dot_h * ( a * a - 1.0) + 1.0;
-    return a * a / (PI * bracket* bracket);
-}
-
-float gemoetry_funciton_schlick_ggx(float dot_prod, float k){
-    return dot_prod / (dot_prod * (1.0 - k) + k);
-}
-
-float geometry_funciton_smith(float n_dot_v, float n_dot_l, float k) {
-    float ggx1 = gemoetry_funciton_schlick_ggx(n_dot_v, k);
-    float ggx2 = gemoetry_funciton_schlick_ggx(n_dot_l, k);
-    return ggx1 * ggx2;
-}
-
-vec3 fresnel_schlick(float cos_theta, vec3 F0){
-    return F0 + (1.0 - F0) * pow(1.0 - cos_theta, 5.0);
-}
+#include "brdf.glsl"
 
 void main() {
     vec3 N = normalize(world_normal);
-    vec3 V = normalize(world_cam_posiiton - world_position);
+    vec3 V = normalize(camera_position.xyz - world_position);
     vec3 R = reflect(-V, N);
 
     vec3 F0 = vec3(0.04);
@@ -0,0 +1,30 @@
+#version 330
+
+layout (location = 0) in vec3 vertex_position;
+layout (location = 1) in vec2 vertex_uv;
+layout (location = 2) in vec3 vertex_normal;
+layout (location = 3) in vec4 vertex_tangent;
+layout (location = 11) in vec2 vertex_uv1;
+
+layout (location = 4) in mat4 instance_model;
+layout (location = 8) in vec2 instance_metallic_roughness;
+
+out vec2 uv;
+out vec2 uv1;
+out vec3 world_position;
+out vec3 world_normal;
+out vec4 world_tangent;
+flat out vec2 metallic_roughness_scale;
+
+#include "camera.glsl"
+
+void main() {
+    uv = vertex_uv;
+    uv1 = vertex_uv1;
+    world_position = vec3(instance_model * vec4(vertex_position, 1.0));
+    world_normal = mat3(instance_model) * vertex_normal;
+    world_tangent = vec4(mat3(instance_model) * vertex_tangent.xyz, vertex_tangent.w);
+    metallic_roughness_scale = instance_metallic_roughness;
+
+    gl_Position = projection * view * vec4(world_position, 1.0);
+}
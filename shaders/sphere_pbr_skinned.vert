@@ -0,0 +1,47 @@
+#version 330
+
+layout (location = 0) in vec3 vertex_position;
+layout (location = 1) in vec2 vertex_uv;
+layout (location = 2) in vec3 vertex_normal;
+layout (location = 3) in vec4 vertex_tangent;
+layout (location = 9) in uvec4 vertex_joints;
+layout (location = 10) in vec4 vertex_weights;
+layout (location = 11) in vec2 vertex_uv1;
+
+out vec2 uv;
+out vec2 uv1;
+out vec3 world_position;
+out vec3 world_normal;
+out vec4 world_tangent;
+flat out vec2 metallic_roughness_scale;
+
+#include "camera.glsl"
+#include "skin.glsl"
+#include "morph.glsl"
+
+uniform mat4 model;
+uniform bool skinned;
+
+void main() {
+    mat4 skin = mat4(1.0);
+    if (skinned) {
+        skin = vertex_weights.x * joint_matrices[vertex_joints.x]
+             + vertex_weights.y * joint_matrices[vertex_joints.y]
+             + vertex_weights.z * joint_matrices[vertex_joints.z]
+             + vertex_weights.w * joint_matrices[vertex_joints.w];
+    }
+    mat4 skinned_model = model * skin;
+
+    vec3 position = vertex_position + morph_delta(MORPH_POSITION);
+    vec3 normal = vertex_normal + morph_delta(MORPH_NORMAL);
+    vec3 tangent = vertex_tangent.xyz + morph_delta(MORPH_TANGENT);
+
+    uv = vertex_uv;
+    uv1 = vertex_uv1;
+    world_position = vec3(skinned_model * vec4(position, 1.0));
+    world_normal = mat3(skinned_model) * normal;
+    world_tangent = vec4(mat3(skinned_model) * tangent, vertex_tangent.w);
+    metallic_roughness_scale = vec2(1.0);
+
+    gl_Position = projection * view * vec4(world_position, 1.0);
+}
@@ -3,6 +3,7 @@
 in vec2 uv;
 in vec3 world_position;
 in vec3 world_normal;
+in vec4 world_tangent;
 
 out vec4 fragment_color;
 
@@ -12,7 +13,7 @@ uniform sampler2D metallic_map;
 uniform sampler2D roughness_map;
 uniform sampler2D ao_map;
 
-uniform vec3 world_cam_posiiton;
+#include "camera.glsl"
 
 #define LIGHT_COUNT 4
 
@@ -44,14 +45,9 @@ vec3 fresnel_schlick(float h_dot_v, vec3 F0){
 vec3 get_normal_worldspace() {
     vec3 normal_tangentspace = texture(normal_map, uv).xyz * 2.0 - 1.0;
 
-    vec3 q1  = dFdx(world_position);
-    vec3 q2  = dFdy(world_position);
-    vec2 st1 = dFdx(uv);
-    vec2 st2 = dFdy(uv);
-
-    vec3 N   = normalize(world_normal);
-    vec3 T  = normalize(q1 * st2.t - q2 * st1.t);
-    vec3 B  = -normalize(cross(N, T));
+    vec3 N = normalize(world_normal);
+    vec3 T = normalize(world_tangent.xyz - dot(world_tangent.xyz, N) * N);
+    vec3 B = cross(N, T) * world_tangent.w;
     mat3 TBN = mat3(T, B, N);
 
     return normalize(TBN * normal_tangentspace);
@@ -64,7 +60,7 @@ void main() {
     float roughness = texture(roughness_map, uv).r;
     float ao = texture(ao_map, uv).r;
 
-    vec3 V = normalize(world_cam_posiiton - world_position);
+    vec3 V = normalize(camera_position.xyz - world_position);
 
     vec3 F0 = vec3(0.04);
     F0 = mix(F0, albedo, metallic);
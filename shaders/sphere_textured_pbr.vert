@@ -3,19 +3,22 @@
 layout (location = 0) in vec3 vertex_position;
 layout (location = 1) in vec2 vertex_uv;
 layout (location = 2) in vec3 vertex_normal;
+layout (location = 3) in vec4 vertex_tangent;
 
 out vec2 uv;
 out vec3 world_position;
 out vec3 world_normal;
+out vec4 world_tangent;
+
+#include "camera.glsl"
 
-uniform mat4 projection;
-uniform mat4 view;
 uniform mat4 model;
 
 void main() {
     uv = vertex_uv;
     world_position = vec3(model * vec4(vertex_position, 1.0));
     world_normal = mat3(model) * vertex_normal;
+    world_tangent = vec4(mat3(model) * vertex_tangent.xyz, vertex_tangent.w);
 
     gl_Position = projection * view * vec4(world_position, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+// Set while capturing HDR screenshots, which keep the linear scene radiance.
+layout (std140) uniform Output {
+    int linear_output;
+};
+
+// Reinhard tone mapping followed by gamma correction.
+vec3 tonemap(vec3 color) {
+    if (linear_output != 0) {
+        return color;
+    }
+    color = color / (color + vec3(1.0));
+    return pow(color, vec3(1.0/2.2));
+}